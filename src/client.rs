@@ -1,49 +1,78 @@
-use aes_gcm::{aead::KeyInit, Aes256Gcm, Key};
+use chrono::{DateTime, Local};
 use colored::*;
-use rustyline_async::{Readline, ReadlineEvent, SharedWriter};
+use rustyline_async::{Readline, ReadlineEvent};
 use std::{
     fs::{self, File},
-    io::{ErrorKind, Write},
+    io::{self, ErrorKind, Write},
     path::{Path, PathBuf},
-    str::SplitWhitespace,
+    str::{self, SplitWhitespace},
+    sync::atomic::Ordering,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    net::TcpStream,
+    time,
 };
-use tokio::net::TcpStream;
 
 use crate::common::{
+    archive::{spawn_tar_extractor, TarCompression},
+    checksum::{spawn_file_digest, to_hex, ChecksumAlgorithm},
+    connection_description,
+    key_exchange::{
+        random_challenge, HandshakeTranscript, KeyExchange, CHALLENGE_LENGTH, PROOF_LENGTH,
+        PUBLIC_KEY_LENGTH,
+    },
     messages::{
-        CdAnswer, DirectoryContents, FileFail, MessageDirectoryContents, MkdirAnswer, RemoveAnswer,
-        RenameAnswer, UploadResult, MESSAGE_CDANSWER, MESSAGE_DIR, MESSAGE_DISCONNECT,
-        MESSAGE_DOWNLOAD_FAIL, MESSAGE_DOWNLOAD_SUCCESS, MESSAGE_INIT, MESSAGE_INIT_ENC,
-        MESSAGE_MKDIRANS, MESSAGE_NOT_ENC, MESSAGE_OK, MESSAGE_REMOVE_ANSWER,
-        MESSAGE_RENAME_ANSWER, MESSAGE_UPLOAD_RESULT,
+        CdAnswer, ChecksumAnswer, CopyAnswer, DetailedDirectoryContents, DfAnswer,
+        DirectoryContents, DuAnswer, FileFail, FileFragment, FileMetadata, FileType, FindEnd,
+        FindQuery, FollowEnd, FollowUpdate, MessageDetailedDirectoryContents,
+        MessageDirectoryContents, MkdirAnswer, ReadAnswer, ReadRange, RemoveAnswer, RenameAnswer,
+        StatAnswer, StreamEnd, UploadCheckAnswer, UploadResult, COPY_OVERWRITE, COPY_RECURSIVE,
+        MESSAGE_AUTH, MESSAGE_AUTH_FAIL, MESSAGE_CDANSWER, MESSAGE_CHALLENGE, MESSAGE_DIR,
+        MESSAGE_DIR_LONG, MESSAGE_DISCONNECT, MESSAGE_DOWNLOAD_FAIL, MESSAGE_DOWNLOAD_SUCCESS,
+        MESSAGE_ENCRYPTION_REQUIRED, MESSAGE_INIT, MESSAGE_INIT_ENC, MESSAGE_INIT_PASSPHRASE,
+        MESSAGE_LOGIN_FAIL, MESSAGE_LOGIN_REQUIRED, MESSAGE_MKDIRANS, MESSAGE_NOT_ENC,
+        MESSAGE_NOT_PASSPHRASE, MESSAGE_OK, MESSAGE_PERMISSIONS, MESSAGE_REMOVE_ANSWER,
+        MESSAGE_RENAME_ANSWER, MESSAGE_UPLOAD_RESULT, REMOVE_PREVIEW, REMOVE_RECURSIVE,
+        RENAME_OVERWRITE,
+    },
+    passphrase::{random_salt, spawn_derive_key},
+    permissions::{Permission, Permissions},
+    tls::{
+        self, is_certificate_mismatch, known_fingerprint, remember_fingerprint, KNOWN_SERVERS_FILE,
     },
     CommunicationAgent, ProgramOptions, ProgramRole, QuickTransferError, QuickTransferStream,
+    StreamFragment, Transport,
 };
 
+const DEFAULT_PREVIEW_LINES: u64 = 10;
+
+/// Outcome of a command operating on the server (failures are printed by the command itself).
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum CommandStatus {
+    Succeeded,
+    Failed,
+}
+
 const INVALID_DIR_NAME_MESSAGE: &str =
     "`directory_name` should be either the name of a directory in current view, \".\" or \"..\".";
 
 // This function is a wrapper to catch errors and (try to) gracefully end a connection in all cases.
 pub async fn handle_client(program_options: &ProgramOptions) -> Result<(), QuickTransferError> {
-    println!(
-        "Welcome to QuickTransfer!\nFor help, type `help`.\nConnecting to server \"{}\" on port {}...",
-        program_options.server_ip_address, program_options.port
-    );
+    if program_options.command.is_empty() {
+        println!(
+            "Welcome to QuickTransfer!\nFor help, type `help`.\nConnecting to server \"{}\" on port {}...",
+            program_options.server_ip_address, program_options.port
+        );
+    }
 
     let stream = connect_to_server(program_options).await?;
+    let stream = start_transport(program_options, stream).await?;
 
-    let mut stream = if let Some(key) = &program_options.aes_key {
-        let key: &Key<Aes256Gcm> = key.into();
-        let cipher = Aes256Gcm::new(key);
-        QuickTransferStream::new_encrypted(
-            stream,
-            cipher,
-            ProgramRole::Client,
-            program_options.timeout,
-        )
-    } else {
-        QuickTransferStream::new_unencrypted(stream, ProgramRole::Client, program_options.timeout)
-    };
+    // The connection becomes encrypted once the session key is agreed on in `serve_client`:
+    let mut stream =
+        QuickTransferStream::new_unencrypted(stream, ProgramRole::Client, program_options.timeout);
 
     let mut agent =
         CommunicationAgent::new(&mut stream, ProgramRole::Client, program_options.timeout);
@@ -62,26 +91,137 @@ async fn serve_client(
     program_options: &ProgramOptions,
     agent: &mut CommunicationAgent<'_>,
 ) -> Result<bool, QuickTransferError> {
-    agent
-        .send_bare_message(if program_options.aes_key.is_some() {
-            MESSAGE_INIT_ENC
-        } else {
-            MESSAGE_INIT
-        })
-        .await?;
+    let key_exchange = KeyExchange::new();
+    let client_challenge = random_challenge();
+    let pre_shared_key = if let Some(passphrase) = &program_options.passphrase {
+        // The key is derived from the passphrase and a salt unique for this connection:
+        let salt = random_salt();
+        agent.send_bare_message(MESSAGE_INIT_PASSPHRASE).await?;
+        agent.send_bare_data(&salt).await?;
+        agent.send_bare_data(&key_exchange.public_key()).await?;
+        agent.send_bare_data(&client_challenge).await?;
+
+        Some(spawn_derive_key(passphrase.clone(), salt).await?)
+    } else if let Some(key) = &program_options.aes_key {
+        agent.send_bare_message(MESSAGE_INIT_ENC).await?;
+        agent.send_bare_data(&key_exchange.public_key()).await?;
+        agent.send_bare_data(&client_challenge).await?;
+
+        Some(*key)
+    } else {
+        agent.send_bare_message(MESSAGE_INIT).await?;
+
+        None
+    };
 
     match agent.receive_bare_message_header().await?.as_str() {
         MESSAGE_NOT_ENC => {
             return Err(QuickTransferError::ServerDoesNotSupportEncryption);
         }
-        MESSAGE_OK => {}
+        MESSAGE_NOT_PASSPHRASE => {
+            return Err(QuickTransferError::ServerDoesNotSupportPassphrase);
+        }
+        MESSAGE_ENCRYPTION_REQUIRED => {
+            return Err(QuickTransferError::EncryptionRequired);
+        }
+        MESSAGE_OK if pre_shared_key.is_none() => {}
+        MESSAGE_CHALLENGE if pre_shared_key.is_some() => {}
         _ => {
             return Err(QuickTransferError::SentInvalidData(ProgramRole::Client));
         }
     }
 
+    if let Some(pre_shared_key) = pre_shared_key {
+        let mut server_public_key = [0_u8; PUBLIC_KEY_LENGTH];
+        let mut server_challenge = [0_u8; CHALLENGE_LENGTH];
+        agent.receive_bare_data(&mut server_public_key).await?;
+        agent.receive_bare_data(&mut server_challenge).await?;
+
+        // Client proves the knowledge of the key first, then the server does:
+        let transcript = HandshakeTranscript {
+            client_public_key: key_exchange.public_key(),
+            client_challenge,
+            server_public_key,
+            server_challenge,
+        };
+        agent.send_bare_message(MESSAGE_AUTH).await?;
+        agent
+            .send_bare_data(&transcript.proof(&pre_shared_key, ProgramRole::Client))
+            .await?;
+
+        match agent.receive_bare_message_header().await?.as_str() {
+            MESSAGE_OK => {}
+            MESSAGE_AUTH_FAIL => {
+                return Err(QuickTransferError::AuthenticationFailed);
+            }
+            _ => {
+                return Err(QuickTransferError::SentInvalidData(ProgramRole::Client));
+            }
+        }
+        let mut server_proof = [0_u8; PROOF_LENGTH];
+        agent.receive_bare_data(&mut server_proof).await?;
+        if !transcript.verify(&pre_shared_key, ProgramRole::Server, &server_proof) {
+            return Err(QuickTransferError::AuthenticationFailed);
+        }
+
+        let session_keys =
+            key_exchange.session_keys(&pre_shared_key, &server_public_key, ProgramRole::Client)?;
+        agent.change_to_encrypted(&session_keys, program_options.rekey_limits);
+    }
+
+    let mut message_received = agent.receive_tcp(false).await?;
+    if agent.read_message_header(&message_received)?.0 == MESSAGE_LOGIN_REQUIRED {
+        let (Some(user_name), Some(password)) =
+            (&program_options.user_name, &program_options.password)
+        else {
+            return Err(QuickTransferError::LoginRequired);
+        };
+        agent.send_login_request(user_name, password).await?;
+
+        // Verifying the password is deliberately slow:
+        message_received = agent.receive_tcp(true).await?;
+        if agent.read_message_header(&message_received)?.0 == MESSAGE_LOGIN_FAIL {
+            return Err(QuickTransferError::LoginFailed);
+        }
+    } else if program_options.user_name.is_some() {
+        return Err(QuickTransferError::ServerDoesNotSupportLogin);
+    }
+
+    // Server tells what the client may do, so that denied commands are not even sent:
+    let message_received =
+        agent.read_message_header_check(&message_received, MESSAGE_PERMISSIONS)?;
+    let permissions: Permissions = agent.read_answer(message_received)?;
+
+    let message_received = agent.receive_tcp(false).await?;
+    let message_received = agent.read_message_header_check(&message_received, MESSAGE_DIR)?;
+    let Ok(MessageDirectoryContents::Success(dir_description)) =
+        agent.read_answer::<MessageDirectoryContents>(message_received)
+    else {
+        eprintln!(
+            "{}{}{}",
+            "Error: An error in reading contents of `".red(),
+            program_options.root_directory.clone().red(),
+            "` occurred.".red(),
+        );
+
+        return Err(QuickTransferError::Other);
+    };
+
+    if !program_options.command.is_empty() {
+        let status =
+            serve_command_from_arguments(&program_options.command.join(" "), agent, permissions)
+                .await?;
+        if status == CommandStatus::Failed {
+            // The connection is closed gracefully anyway, the failure is reported by the exit status:
+            let _ = agent.send_disconnect_message().await;
+            return Err(QuickTransferError::Other);
+        }
+
+        return Ok(true);
+    }
+
     println!(
-        "{}{}{}{}{}",
+        "{}{}{}{}{}{}",
         "Successfully connected to ".green().bold(),
         format!(
             "[{}]:{}",
@@ -89,51 +229,34 @@ async fn serve_client(
         )
         .on_green()
         .white(),
+        program_options
+            .user_name
+            .as_ref()
+            .map(|user_name| format!(" as `{}`", user_name))
+            .unwrap_or_default()
+            .green()
+            .bold(),
         "! (connection ".green().bold(),
-        if program_options.aes_key.is_some() {
-            "encrypted"
-        } else {
-            "not encrypted"
-        }
+        connection_description(
+            program_options.tls,
+            program_options.aes_key.is_some() || program_options.passphrase.is_some()
+        )
         .green()
         .bold(),
         ")".green().bold(),
     );
 
+    if permissions != Permissions::FULL {
+        println!(
+            "{}",
+            format!("Your permissions: {}.", permissions.names()).yellow()
+        );
+    }
+
     let rl = Readline::new(String::from("QuickTransfer> ")).unwrap();
     let mut writer = rl.1;
     let mut rl = rl.0;
-    let message_received = agent.receive_tcp(false).await?;
-    let message_received = agent.read_message_header_check(&message_received, MESSAGE_DIR)?;
-    if let Ok(dir_description) = agent.read_answer::<MessageDirectoryContents>(message_received) {
-        if let MessageDirectoryContents::Success(dir_description) = &dir_description {
-            print_directory_contents(dir_description, &mut writer)?;
-        } else {
-            writeln!(
-                writer,
-                "{}{}{}",
-                "Error: An error in reading contents of `".red(),
-                program_options.root_directory.clone().red(),
-                "` occurred.".red(),
-            )
-            .map_err(|_| QuickTransferError::Stdout)?;
-            writer.flush().map_err(|_| QuickTransferError::Stdout)?;
-
-            return Err(QuickTransferError::Other);
-        }
-    } else {
-        writeln!(
-            writer,
-            "{}{}{}",
-            "Error: An error in reading contents of `".red(),
-            program_options.root_directory.clone().red(),
-            "` occurred.".red(),
-        )
-        .map_err(|_| QuickTransferError::Stdout)?;
-        writer.flush().map_err(|_| QuickTransferError::Stdout)?;
-
-        return Err(QuickTransferError::Other);
-    }
+    print_directory_contents(&dir_description, false, &mut writer)?;
 
     // Pre-print user help:
     let mut user_help = String::new();
@@ -175,27 +298,6 @@ async fn serve_client(
                         let command = input_splitted.next();
 
                         match command {
-                            Some("cd") => {
-                                serve_cd_command(input, &mut writer, agent).await?;
-                            }
-                            Some("ls") => {
-                                serve_ls_command(&mut input_splitted, &mut writer, agent).await?;
-                            }
-                            Some("download") => {
-                                serve_download_command(input, &mut writer, agent, &mut rl).await?;
-                            }
-                            Some("upload") => {
-                                serve_upload_command(input, &mut writer, agent, &mut rl).await?;
-                            }
-                            Some("mkdir") => {
-                                serve_mkdir_command(input, &mut writer, agent).await?;
-                            }
-                            Some("mv") => {
-                                serve_mv_command(input, &mut writer, agent).await?;
-                            }
-                            Some("rm") => {
-                                serve_rm_command(input, &mut writer, agent).await?;
-                            }
                             Some("clear") => {
                                 rl.clear().map_err(|_| QuickTransferError::Stdout)?;
                             }
@@ -206,13 +308,16 @@ async fn serve_client(
                                 Write::write(&mut writer, user_help.as_bytes()).map_err(|_| QuickTransferError::Stdout)?;
                             }
                             Some(command) => {
-                                writeln!(
-                                    writer,
-                                    "{}{}{}",
-                                    "Error: Command `".red(),
-                                    command.red(),
-                                    "` does not exist!".red(),
-                                ).map_err(|_| QuickTransferError::Stdout)?;
+                                let status = serve_command(input, &mut writer, agent, permissions, Some(&mut rl)).await?;
+                                if status.is_none() {
+                                    writeln!(
+                                        writer,
+                                        "{}{}{}",
+                                        "Error: Command `".red(),
+                                        command.red(),
+                                        "` does not exist!".red(),
+                                    ).map_err(|_| QuickTransferError::Stdout)?;
+                                }
                             }
                             None => {}
                         }
@@ -223,12 +328,120 @@ async fn serve_client(
     }
 }
 
+/// Serves a command given in program arguments. Messages are printed to stderr, so that stdout
+/// can carry file contents.
+async fn serve_command_from_arguments(
+    input: &str,
+    agent: &mut CommunicationAgent<'_>,
+    permissions: Permissions,
+) -> Result<CommandStatus, QuickTransferError> {
+    let mut writer = io::stderr();
+
+    let Some(status) = serve_command(input, &mut writer, agent, permissions, None).await? else {
+        let command = input.split_whitespace().next().unwrap_or(input);
+        writeln!(
+            writer,
+            "{}{}{}",
+            "Error: Command `".red(),
+            command.red(),
+            "` does not exist!".red(),
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Failed);
+    };
+
+    Ok(status)
+}
+
+/// Serves a command operating on the server. Returns its status (`None` if such a command
+/// does not exist). `rl` is `None` if the command has been given in program arguments.
+async fn serve_command(
+    input: &str,
+    writer: &mut impl Write,
+    agent: &mut CommunicationAgent<'_>,
+    permissions: Permissions,
+    rl: Option<&mut Readline>,
+) -> Result<Option<CommandStatus>, QuickTransferError> {
+    let mut input_splitted = input.split_whitespace();
+    let command = input_splitted.next();
+
+    // Commands not permitted on the server are explained instead of being sent:
+    if let Some(permission) = command.and_then(command_permission) {
+        if !permissions.allows(permission) {
+            print_permission_denied(permission, writer)?;
+            return Ok(Some(CommandStatus::Failed));
+        }
+    }
+
+    let result = match command {
+        Some("cd") => serve_cd_command(input, writer, agent).await,
+        Some("ls") => serve_ls_command(&mut input_splitted, writer, agent).await,
+        Some("download") => serve_download_command(input, writer, agent, rl).await,
+        Some("upload") => serve_upload_command(input, writer, agent, rl).await,
+        Some("cat") | Some("head") | Some("tail") | Some("read") => {
+            serve_read_command(input, writer, agent, rl).await
+        }
+        Some("find") => serve_find_command(input, writer, agent, rl).await,
+        Some("du") => serve_du_command(input, writer, agent).await,
+        Some("df") => serve_df_command(&mut input_splitted, writer, agent).await,
+        Some("checksum") => serve_checksum_command(input, writer, agent, rl).await,
+        Some("stat") => serve_stat_command(input, writer, agent).await,
+        Some("cp") => serve_cp_command(&mut input_splitted, writer, agent, rl).await,
+        Some("mkdir") => serve_mkdir_command(input, writer, agent).await,
+        Some("mv") => serve_mv_command(input, writer, agent).await,
+        Some("rm") => serve_rm_command(input, writer, agent, rl).await,
+        _ => {
+            return Ok(None);
+        }
+    };
+
+    // A request may also be denied by the server (e.g. when it would overwrite a file):
+    match result {
+        Err(QuickTransferError::PermissionDenied(permission)) => {
+            print_permission_denied(permission, writer)?;
+
+            Ok(Some(CommandStatus::Failed))
+        }
+        result => result.map(Some),
+    }
+}
+
+/// Returns the permission needed for a command.
+fn command_permission(command: &str) -> Option<Permission> {
+    match command {
+        "ls" | "find" | "du" | "stat" => Some(Permission::List),
+        "download" | "cat" | "head" | "tail" | "read" | "checksum" => Some(Permission::Download),
+        "upload" | "cp" => Some(Permission::Upload),
+        "mkdir" => Some(Permission::Mkdir),
+        "mv" => Some(Permission::Rename),
+        "rm" => Some(Permission::Delete),
+        _ => None,
+    }
+}
+
+fn print_permission_denied(
+    permission: Permission,
+    writer: &mut impl Write,
+) -> Result<(), QuickTransferError> {
+    writeln!(
+        writer,
+        "{}",
+        format!(
+            "Error: {}",
+            QuickTransferError::PermissionDenied(permission)
+        )
+        .red()
+    )
+    .map_err(|_| QuickTransferError::Stdout)
+}
+
 /// Serves a `cd` command typed by user.
 async fn serve_cd_command(
     input: &str,
-    writer: &mut SharedWriter,
+    writer: &mut impl Write,
     agent: &mut CommunicationAgent<'_>,
-) -> Result<(), QuickTransferError> {
+) -> Result<CommandStatus, QuickTransferError> {
     let directory_name = input.split_once(char::is_whitespace);
     if directory_name.is_none() {
         writeln!(
@@ -239,7 +452,7 @@ async fn serve_cd_command(
         )
         .map_err(|_| QuickTransferError::Stdout)?;
 
-        return Ok(());
+        return Ok(CommandStatus::Failed);
     }
 
     let directory_name = String::from(directory_name.unwrap().1);
@@ -253,7 +466,7 @@ async fn serve_cd_command(
         )
         .map_err(|_| QuickTransferError::Stdout)?;
 
-        return Ok(());
+        return Ok(CommandStatus::Failed);
     }
 
     agent.send_change_directory(&directory_name).await?;
@@ -295,56 +508,126 @@ async fn serve_cd_command(
         }
         CdAnswer::Success(dir_description) => {
             if let MessageDirectoryContents::Success(dir_description) = dir_description {
-                print_directory_contents(&dir_description, writer)?;
+                print_directory_contents(&dir_description, false, writer)?;
             }
+
+            return Ok(CommandStatus::Succeeded);
         }
     }
 
-    Ok(())
+    Ok(CommandStatus::Failed)
+}
+
+/// Options of a `ls` command.
+#[derive(Default)]
+struct LsOptions {
+    long: bool,
+    all: bool,
+    by_time: bool,
+    by_size: bool,
+    reverse: bool,
+}
+
+impl LsOptions {
+    /// Parses `ls` arguments (flags can be combined, e.g. `-la`).
+    fn parse(arguments: &mut SplitWhitespace<'_>) -> Option<LsOptions> {
+        let mut options = LsOptions::default();
+
+        for argument in arguments {
+            let flags = argument
+                .strip_prefix('-')
+                .filter(|flags| !flags.is_empty())?;
+            for flag in flags.chars() {
+                match flag {
+                    'l' => options.long = true,
+                    'a' => options.all = true,
+                    't' => options.by_time = true,
+                    'S' => options.by_size = true,
+                    'r' => options.reverse = true,
+                    _ => return None,
+                }
+            }
+        }
+
+        Some(options)
+    }
+
+    /// Whether metadata of directory positions is needed (plain listings use a "LS" message).
+    fn needs_metadata(&self) -> bool {
+        self.long || self.by_time || self.by_size || self.reverse
+    }
 }
 
 /// Serves a `ls` command typed by user.
 async fn serve_ls_command(
     input_splitted: &mut SplitWhitespace<'_>,
-    writer: &mut SharedWriter,
+    writer: &mut impl Write,
     agent: &mut CommunicationAgent<'_>,
-) -> Result<(), QuickTransferError> {
-    if input_splitted.next().is_some() {
-        writeln!(writer, "{}", "Usage: `ls`".to_string().red())
+) -> Result<CommandStatus, QuickTransferError> {
+    let Some(options) = LsOptions::parse(input_splitted) else {
+        writeln!(writer, "{}", "Usage: `ls [-l] [-a] [-t] [-S] [-r]`".red())
             .map_err(|_| QuickTransferError::Stdout)?;
 
-        return Ok(());
-    }
-    agent.send_list_directory().await?;
+        return Ok(CommandStatus::Failed);
+    };
 
-    let message = agent.receive_tcp(false).await?;
-    let message = agent.read_message_header_check(&message, MESSAGE_DIR)?;
-    let dir_description = agent.read_answer(message)?;
+    if options.needs_metadata() {
+        agent.send_list_directory_long().await?;
 
-    if let MessageDirectoryContents::Success(dir_description) = dir_description {
-        print_directory_contents(&dir_description, writer)?;
+        let message = agent.receive_tcp(false).await?;
+        let message = agent.read_message_header_check(&message, MESSAGE_DIR_LONG)?;
+        let dir_description = agent.read_answer(message)?;
+
+        if let MessageDetailedDirectoryContents::Success(dir_description) = dir_description {
+            print_detailed_directory_contents(dir_description, &options, writer)?;
+
+            return Ok(CommandStatus::Succeeded);
+        }
     } else {
-        writeln!(
-            writer,
-            "{}",
-            "Error: An error in reading contents of the directory occurred.".red(),
-        )
-        .map_err(|_| QuickTransferError::Stdout)?;
+        agent.send_list_directory().await?;
+
+        let message = agent.receive_tcp(false).await?;
+        let message = agent.read_message_header_check(&message, MESSAGE_DIR)?;
+        let dir_description = agent.read_answer(message)?;
+
+        if let MessageDirectoryContents::Success(dir_description) = dir_description {
+            print_directory_contents(&dir_description, options.all, writer)?;
+
+            return Ok(CommandStatus::Succeeded);
+        }
     }
 
-    Ok(())
+    writeln!(
+        writer,
+        "{}",
+        "Error: An error in reading contents of the directory occurred.".red(),
+    )
+    .map_err(|_| QuickTransferError::Stdout)?;
+
+    Ok(CommandStatus::Failed)
 }
 
 /// Serves a `download` command typed by user.
 async fn serve_download_command(
     input: &str,
-    writer: &mut SharedWriter,
+    writer: &mut impl Write,
     agent: &mut CommunicationAgent<'_>,
-    rl: &mut Readline,
-) -> Result<(), QuickTransferError> {
+    rl: Option<&mut Readline>,
+) -> Result<CommandStatus, QuickTransferError> {
     let file_name = parse_file_name(input, "download <file_path>", "<file_path>", writer);
     let Some(file_name) = file_name else {
-        return Ok(());
+        return Ok(CommandStatus::Failed);
+    };
+
+    if let Some(arguments) = file_name.strip_prefix("--tar") {
+        if arguments.is_empty() || arguments.starts_with(char::is_whitespace) {
+            return serve_download_tar_command(arguments.trim_start(), writer, agent, rl).await;
+        }
+    }
+
+    let Some((file_name, to_stdout)) = parse_stdout_destination(&file_name, rl.is_some(), writer)
+    else {
+        return Ok(CommandStatus::Failed);
     };
 
     agent.send_download_request(&file_name).await?;
@@ -354,48 +637,28 @@ async fn serve_download_command(
     match header_received.as_str() {
         MESSAGE_DOWNLOAD_FAIL => {
             let download_fail = agent.read_answer(message)?;
-            match download_fail {
-                FileFail::FileDoesNotExist => {
-                    writeln!(
-                        writer,
-                        "{}{}{}",
-                        "Error: File `".red(),
-                        file_name.red(),
-                        "` does not exist!".red(),
-                    )
-                    .map_err(|_| QuickTransferError::Stdout)?;
-                }
-                FileFail::IllegalFile => {
-                    writeln!(
-                        writer,
-                        "{}{}{}",
-                        "Error: You don't have access to file `".red(),
-                        file_name.red(),
-                        "`!".red(),
-                    )
-                    .map_err(|_| QuickTransferError::Stdout)?;
-                }
-                FileFail::ErrorOpeningFile => {
-                    writeln!(
-                        writer,
-                        "{}{}{}",
-                        "Error: Error opening file `".red(),
-                        file_name.red(),
-                        "`!".red(),
-                    )
-                    .map_err(|_| QuickTransferError::Stdout)?;
-                }
-                FileFail::ErrorCreatingFile => {
-                    writeln!(
-                        writer,
-                        "{}{}{}",
-                        "Error: Error creating file `".red(),
-                        file_name.red(),
-                        "`!".red(),
-                    )
-                    .map_err(|_| QuickTransferError::Stdout)?;
+            print_download_fail(download_fail, &file_name, writer)?;
+        }
+        MESSAGE_DOWNLOAD_SUCCESS if to_stdout => {
+            let (file_size, _) = agent.read_u64(message)?;
+            let mut stdout = io::stdout();
+
+            if let Err(error) = agent
+                .receive_file(&mut stdout, file_size, Path::new("-"), false)
+                .await
+            {
+                if let QuickTransferError::WritingFile { .. } = error {
+                    writeln!(writer, "{}", "Error: Error writing to stdout.".red())
+                        .map_err(|_| QuickTransferError::Stdout)?;
+
+                    return Ok(CommandStatus::Failed);
+                } else {
+                    return Err(error);
                 }
             }
+            stdout.flush().map_err(|_| QuickTransferError::Stdout)?;
+
+            return Ok(CommandStatus::Succeeded);
         }
         MESSAGE_DOWNLOAD_SUCCESS => {
             let file_name_truncated = Path::new(&file_name)
@@ -414,7 +677,9 @@ async fn serve_download_command(
 
             writeln!(writer, "Downloading file `{}`...", file_name_truncated)
                 .map_err(|_| QuickTransferError::Stdout)?;
-            rl.flush().map_err(|_| QuickTransferError::Stdout)?;
+            if let Some(rl) = rl {
+                rl.flush().map_err(|_| QuickTransferError::Stdout)?;
+            }
             if let Err(error) = agent
                 .receive_file(opened_file, file_size, file_path.as_path(), false)
                 .await
@@ -429,7 +694,7 @@ async fn serve_download_command(
                     )
                     .map_err(|_| QuickTransferError::Stdout)?;
 
-                    return Ok(());
+                    return Ok(CommandStatus::Failed);
                 } else {
                     return Err(error);
                 }
@@ -440,115 +705,1448 @@ async fn serve_download_command(
                 file_name_truncated
             )
             .map_err(|_| QuickTransferError::Stdout)?;
+
+            return Ok(CommandStatus::Succeeded);
         }
         &_ => {
             return Err(QuickTransferError::SentInvalidData(ProgramRole::Client));
         }
     }
 
-    Ok(())
+    Ok(CommandStatus::Failed)
+}
+
+/// Serves a `download --tar` command typed by user (`arguments` are the ones following `--tar`).
+async fn serve_download_tar_command(
+    arguments: &str,
+    writer: &mut impl Write,
+    agent: &mut CommunicationAgent<'_>,
+    rl: Option<&mut Readline>,
+) -> Result<CommandStatus, QuickTransferError> {
+    let mut compression = TarCompression::None;
+    let mut extract = false;
+    let mut directory_name = arguments;
+
+    loop {
+        let (option, rest) = directory_name
+            .split_once(char::is_whitespace)
+            .unwrap_or((directory_name, ""));
+        match option {
+            "-z" | "--gzip" => compression = TarCompression::Gzip,
+            "-x" | "--extract" => extract = true,
+            _ => break,
+        }
+        directory_name = rest.trim_start();
+    }
+
+    let Some((directory_name, to_stdout)) =
+        parse_stdout_destination(directory_name, rl.is_some(), writer)
+    else {
+        return Ok(CommandStatus::Failed);
+    };
+    let extract = extract && !to_stdout;
+
+    if directory_name.is_empty() {
+        writeln!(
+            writer,
+            "{}",
+            "Usage: `download --tar [-z] [-x] <directory_path> [-]`. `<directory_path>` should be the path of a directory relative to current view."
+                .red(),
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Failed);
+    }
+    agent
+        .send_download_tar_request(&directory_name, compression)
+        .await?;
+    let message = agent.receive_tcp(false).await?;
+    let (header_received, message_body) = agent.read_message_header(&message)?;
+
+    if header_received == MESSAGE_DOWNLOAD_FAIL {
+        let download_fail = agent.read_answer(message_body)?;
+        print_download_fail(download_fail, &directory_name, writer)?;
+
+        return Ok(CommandStatus::Failed);
+    }
+
+    let archive_name = Path::new(&directory_name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("archive")
+        .to_string();
+
+    let mut archive_file: Option<Box<dyn Write>> = None;
+    let mut extractor = None;
+    let mut saving_failed = false;
+    if to_stdout {
+        archive_file = Some(Box::new(io::stdout()));
+    } else if extract {
+        writeln!(
+            writer,
+            "Downloading and extracting directory `{}`...",
+            directory_name
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+        extractor = Some(spawn_tar_extractor(PathBuf::from("./"), compression));
+    } else {
+        let archive_file_name = format!("{}.{}", archive_name, compression.file_extension());
+        writeln!(
+            writer,
+            "Downloading directory `{}` as `{}`...",
+            directory_name, archive_file_name
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        match File::create(&archive_file_name) {
+            Ok(file) => archive_file = Some(Box::new(file)),
+            Err(_) => saving_failed = true,
+        }
+    }
+    if let Some(rl) = rl {
+        rl.flush().map_err(|_| QuickTransferError::Stdout)?;
+    }
+
+    // The stream has to be received completely even if saving fails:
+    let mut fragment = agent.read_stream_fragment(&message)?;
+    let stream_end = loop {
+        match fragment {
+            StreamFragment::Data(data) => {
+                if let Some(file) = &mut archive_file {
+                    if file.write_all(&data).is_err() {
+                        saving_failed = true;
+                        archive_file = None;
+                    }
+                }
+                if let Some((fragments, _)) = &extractor {
+                    // If the extraction has stopped, the error is read from its handle below:
+                    let _ = fragments.send(data).await;
+                }
+            }
+            StreamFragment::End(stream_end) => break stream_end,
+        }
+        fragment = agent.receive_stream_fragment(false).await?;
+    };
+
+    if let Some((fragments, handle)) = extractor {
+        drop(fragments);
+        saving_failed = !matches!(handle.await, Ok(Ok(())));
+    }
+    if let Some(file) = &mut archive_file {
+        saving_failed |= file.flush().is_err();
+    }
+
+    if stream_end == StreamEnd::Fail {
+        writeln!(
+            writer,
+            "{}{}{}",
+            "Error: Server failed to archive directory `".red(),
+            directory_name.red(),
+            "`.".red(),
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+    } else if saving_failed {
+        writeln!(
+            writer,
+            "{}{}{}",
+            "Error: Error saving directory `".red(),
+            directory_name.red(),
+            "`.".red(),
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+    } else {
+        writeln!(
+            writer,
+            "Successfully downloaded directory `{}`!",
+            directory_name
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Succeeded);
+    }
+
+    Ok(CommandStatus::Failed)
+}
+
+/// Prints the reason of a failed download.
+fn print_download_fail(
+    download_fail: FileFail,
+    file_name: &str,
+    writer: &mut impl Write,
+) -> Result<(), QuickTransferError> {
+    match download_fail {
+        FileFail::FileDoesNotExist => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: File `".red(),
+                file_name.red(),
+                "` does not exist!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        FileFail::IllegalFile => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: You don't have access to file `".red(),
+                file_name.red(),
+                "`!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        FileFail::ErrorOpeningFile => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: Error opening file `".red(),
+                file_name.red(),
+                "`!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        FileFail::ErrorCreatingFile => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: Error creating file `".red(),
+                file_name.red(),
+                "`!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        FileFail::ErrorWritingFile => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: Error writing file `".red(),
+                file_name.red(),
+                "`!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        FileFail::TransferInterrupted => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: Transfer of file `".red(),
+                file_name.red(),
+                "` has been interrupted!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        FileFail::NotEnoughSpace => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: Not enough space for file `".red(),
+                file_name.red(),
+                "`!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Serves an `upload` command typed by user.
+async fn serve_upload_command(
+    input: &str,
+    writer: &mut impl Write,
+    agent: &mut CommunicationAgent<'_>,
+    rl: Option<&mut Readline>,
+) -> Result<CommandStatus, QuickTransferError> {
+    let file_name = parse_file_name(input, "upload [-f] <file_path>", "<file_path>", writer);
+    let Some(file_name) = file_name else {
+        return Ok(CommandStatus::Failed);
+    };
+    let (force, file_name) = match file_name.strip_prefix("-f ") {
+        Some(file_name) => (true, file_name.trim_start().to_string()),
+        None => (false, file_name),
+    };
+
+    if let Some(remote_file_name) = file_name.strip_prefix('-') {
+        if remote_file_name.is_empty() || remote_file_name.starts_with(char::is_whitespace) {
+            return serve_upload_stdin_command(remote_file_name.trim_start(), writer, agent, rl)
+                .await;
+        }
+    }
+
+    let file_path = Path::new(&file_name);
+
+    if !fs::exists(file_path).unwrap() || !file_path.is_file() {
+        writeln!(
+            writer,
+            "{}{}{}",
+            "Error: File `".red(),
+            file_name.red(),
+            "` does not exist!".red(),
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Failed);
+    }
+
+    let Ok(opened_file) = File::open(file_path) else {
+        writeln!(
+            writer,
+            "{}{}{}",
+            "Error opening file `".red(),
+            file_name.red(),
+            "`.".red(),
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Failed);
+    };
+
+    let Ok(file_size) = opened_file.metadata() else {
+        writeln!(
+            writer,
+            "{}{}{}",
+            "Error reading size of file `".red(),
+            file_name.red(),
+            "`.".red(),
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Failed);
+    };
+    let file_name_truncated = Path::new(&file_name)
+        .file_name()
+        .map(|string| string.to_str().map(|string| string.to_string()))
+        .unwrap_or(Some(file_name.clone()))
+        .unwrap_or(file_name.clone());
+
+    if !force
+        && remote_file_unchanged(file_path, file_size.len(), &file_name_truncated, agent).await?
+    {
+        writeln!(
+            writer,
+            "{}",
+            format!("File `{}` is unchanged, upload skipped.", file_name).green()
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Succeeded);
+    }
+
+    writeln!(writer, "Uploading file `{}`...", file_name)
+        .map_err(|_| QuickTransferError::Stdout)?;
+    if let Some(rl) = rl {
+        rl.flush().map_err(|_| QuickTransferError::Stdout)?;
+    }
+    let upload_result = agent
+        .upload_file(
+            opened_file,
+            file_size.len(),
+            &file_name_truncated,
+            file_path,
+        )
+        .await?;
+
+    print_upload_result(upload_result, &file_name, writer)
+}
+
+/// Checks whether the upload destination on the server has the same size and digest
+/// as the local file (the local file is hashed only if the sizes match).
+async fn remote_file_unchanged(
+    file_path: &Path,
+    file_size: u64,
+    file_name: &str,
+    agent: &mut CommunicationAgent<'_>,
+) -> Result<bool, QuickTransferError> {
+    let algorithm = ChecksumAlgorithm::Blake3;
+    let UploadCheckAnswer::Digest(remote_digest) = agent
+        .check_remote_upload(file_name, file_size, algorithm)
+        .await?
+    else {
+        return Ok(false);
+    };
+
+    let (_, local_digest) = spawn_file_digest(file_path.to_path_buf(), algorithm);
+
+    Ok(matches!(local_digest.await, Ok(Ok(local_digest)) if local_digest == remote_digest))
+}
+
+/// Serves an `upload - <file_name>` command (uploads standard input until EOF).
+async fn serve_upload_stdin_command(
+    file_name: &str,
+    writer: &mut impl Write,
+    agent: &mut CommunicationAgent<'_>,
+    rl: Option<&mut Readline>,
+) -> Result<CommandStatus, QuickTransferError> {
+    if rl.is_some() {
+        writeln!(
+            writer,
+            "{}",
+            "Error: Standard input (`-`) can be uploaded only with a command given in program arguments."
+                .red(),
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Failed);
+    }
+
+    if file_name.is_empty() {
+        writeln!(
+            writer,
+            "{}",
+            "Usage: `upload - <file_name>`. `<file_name>` is the name of the file created in current view."
+                .red(),
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Failed);
+    }
+
+    writeln!(writer, "Uploading standard input as `{}`...", file_name)
+        .map_err(|_| QuickTransferError::Stdout)?;
+    let stream_end = agent
+        .send_upload_stream(tokio::io::stdin(), file_name)
+        .await?;
+
+    let message = agent.receive_tcp(false).await?;
+    let message = agent.read_message_header_check(&message, MESSAGE_UPLOAD_RESULT)?;
+    let upload_result = agent.read_answer(message)?;
+
+    let status = print_upload_result(upload_result, file_name, writer)?;
+    if stream_end == StreamEnd::Fail {
+        writeln!(
+            writer,
+            "{}",
+            "Error: An error reading standard input has occurred.".red(),
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Failed);
+    }
+
+    Ok(status)
+}
+
+/// Prints the result of an upload.
+fn print_upload_result(
+    upload_result: UploadResult,
+    file_name: &str,
+    writer: &mut impl Write,
+) -> Result<CommandStatus, QuickTransferError> {
+    match upload_result {
+        UploadResult::Fail(fail) => {
+            match fail {
+                FileFail::ErrorCreatingFile => {
+                    writeln!(writer, "Uploading file `{}` failed. An error creating the file on server occurred.", file_name).map_err(|_| QuickTransferError::Stdout)?;
+                }
+                FileFail::ErrorWritingFile => {
+                    writeln!(
+                        writer,
+                        "Uploading file `{}` failed. An error writing the file on server occurred.",
+                        file_name
+                    )
+                    .map_err(|_| QuickTransferError::Stdout)?;
+                }
+                FileFail::NotEnoughSpace => {
+                    writeln!(
+                        writer,
+                        "Uploading file `{}` failed. There is not enough space on server.",
+                        file_name
+                    )
+                    .map_err(|_| QuickTransferError::Stdout)?;
+                }
+                FileFail::IllegalFile => {
+                    writeln!(
+                        writer,
+                        "Uploading file `{}` failed. You don't have access to this file on server.",
+                        file_name
+                    )
+                    .map_err(|_| QuickTransferError::Stdout)?;
+                }
+                _ => {
+                    writeln!(writer, "Uploading file `{}` failed.", file_name)
+                        .map_err(|_| QuickTransferError::Stdout)?;
+                }
+            }
+        }
+        UploadResult::Success => {
+            writeln!(writer, "Successfully uploaded file `{}`!", file_name)
+                .map_err(|_| QuickTransferError::Stdout)?;
+
+            return Ok(CommandStatus::Succeeded);
+        }
+    }
+
+    Ok(CommandStatus::Failed)
+}
+
+/// Serves `cat`, `head`, `tail` and `read` commands typed by user.
+/// In non-interactive mode file contents are printed to stdout.
+async fn serve_read_command(
+    input: &str,
+    writer: &mut impl Write,
+    agent: &mut CommunicationAgent<'_>,
+    rl: Option<&mut Readline>,
+) -> Result<CommandStatus, QuickTransferError> {
+    let interactive = rl.is_some();
+    let (command, mut arguments) = split_first_argument(input);
+    let mut follow = false;
+
+    let (range, usage) = match command {
+        "cat" => (
+            // The server bounds the length by `MAX_READ_SIZE`:
+            Some(ReadRange::Bytes {
+                offset: 0,
+                length: u64::MAX,
+            }),
+            "cat <file_path>",
+        ),
+        "head" | "tail" => {
+            let mut lines = Some(DEFAULT_PREVIEW_LINES);
+            loop {
+                match split_first_argument(arguments) {
+                    ("-n", rest) => {
+                        let (lines_argument, rest) = split_first_argument(rest);
+                        lines = lines_argument.parse().ok();
+                        arguments = rest;
+                    }
+                    ("-f", rest) if command == "tail" => {
+                        follow = true;
+                        arguments = rest;
+                    }
+                    _ => break,
+                }
+            }
+
+            if command == "head" {
+                (
+                    lines.map(ReadRange::HeadLines),
+                    "head [-n <lines>] <file_path>",
+                )
+            } else {
+                (
+                    lines.map(ReadRange::TailLines),
+                    "tail [-f] [-n <lines>] <file_path>",
+                )
+            }
+        }
+        _ => {
+            let (offset, rest) = split_first_argument(arguments);
+            let (length, rest) = split_first_argument(rest);
+            arguments = rest;
+
+            let range = offset
+                .parse()
+                .ok()
+                .zip(length.parse().ok())
+                .map(|(offset, length)| ReadRange::Bytes { offset, length });
+            (range, "read <offset> <length> <file_path>")
+        }
+    };
+
+    let (Some(range), false) = (range, arguments.is_empty()) else {
+        writeln!(
+            writer,
+            "{}{}{}",
+            "Usage: `".red(),
+            usage.red(),
+            "`. `<file_path>` should be the path of a file relative to current view.".red(),
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Failed);
+    };
+    let file_name = arguments;
+
+    match agent.read_remote_file(file_name, &range).await? {
+        ReadAnswer::FileDoesNotExist => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: File `".red(),
+                file_name.red(),
+                "` does not exist!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        ReadAnswer::IllegalFile => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: You don't have access to file `".red(),
+                file_name.red(),
+                "`!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        ReadAnswer::ErrorReading => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: An error reading file `".red(),
+                file_name.red(),
+                "` has occurred.".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        ReadAnswer::Success(fragment) => {
+            let is_binary = fragment.content.contains(&0)
+                || matches!(str::from_utf8(&fragment.content), Err(error) if error.error_len().is_some());
+
+            // Bytes printed to stdout are not decoded, so that they can be redirected:
+            if !interactive {
+                write_to_stdout(&fragment.content)?;
+            } else if is_binary {
+                writeln!(
+                    writer,
+                    "{}{}{}",
+                    "Warning: File `".yellow(),
+                    file_name.yellow(),
+                    "` seems to be binary, so its contents are not displayed. Use `download` instead."
+                        .yellow(),
+                )
+                .map_err(|_| QuickTransferError::Stdout)?;
+
+                return Ok(CommandStatus::Failed);
+            } else {
+                print_file_fragment(&fragment, writer)?;
+            }
+
+            let fragment_end = fragment.offset + u64::try_from(fragment.content.len()).unwrap();
+            if fragment.truncated && !follow {
+                writeln!(
+                    writer,
+                    "{}",
+                    format!(
+                        "(Displayed bytes {}-{} of {}.)",
+                        fragment.offset, fragment_end, fragment.file_size
+                    )
+                    .yellow(),
+                )
+                .map_err(|_| QuickTransferError::Stdout)?;
+            }
+
+            if follow {
+                return serve_tail_follow(file_name, fragment_end, writer, agent, rl).await;
+            }
+
+            return Ok(CommandStatus::Succeeded);
+        }
+    }
+
+    Ok(CommandStatus::Failed)
+}
+
+/// Displays bytes appended to a remote file (starting at byte `offset`) until user presses Ctrl-C.
+/// In non-interactive mode file contents are printed to stdout.
+async fn serve_tail_follow(
+    file_name: &str,
+    offset: u64,
+    writer: &mut impl Write,
+    agent: &mut CommunicationAgent<'_>,
+    rl: Option<&mut Readline>,
+) -> Result<CommandStatus, QuickTransferError> {
+    let interactive = rl.is_some();
+
+    let follow_end = agent
+        .follow_remote_file(
+            file_name,
+            offset,
+            |update| {
+                match update {
+                    FollowUpdate::Appended(content) if !interactive => {
+                        return write_to_stdout(&content);
+                    }
+                    FollowUpdate::Appended(content) => {
+                        write!(writer, "{}", String::from_utf8_lossy(&content))
+                    }
+                    FollowUpdate::Truncated => writeln!(
+                        writer,
+                        "{}",
+                        format!("\n(File `{}` has been truncated.)", file_name).yellow()
+                    ),
+                    FollowUpdate::Rotated => writeln!(
+                        writer,
+                        "{}",
+                        format!(
+                            "\n(File `{}` has been replaced, following the new one.)",
+                            file_name
+                        )
+                        .yellow()
+                    ),
+                }
+                .map_err(|_| QuickTransferError::Stdout)
+            },
+            interrupted(rl),
+        )
+        .await?;
+
+    match follow_end {
+        FollowEnd::Stopped => return Ok(CommandStatus::Succeeded),
+        FollowEnd::FileDoesNotExist => writeln!(
+            writer,
+            "{}{}{}",
+            "Error: File `".red(),
+            file_name.red(),
+            "` does not exist!".red(),
+        ),
+        FollowEnd::IllegalFile => writeln!(
+            writer,
+            "{}{}{}",
+            "Error: You don't have access to file `".red(),
+            file_name.red(),
+            "`!".red(),
+        ),
+        FollowEnd::ErrorReading => writeln!(
+            writer,
+            "{}{}{}",
+            "Error: An error reading file `".red(),
+            file_name.red(),
+            "` has occurred.".red(),
+        ),
+    }
+    .map_err(|_| QuickTransferError::Stdout)?;
+
+    Ok(CommandStatus::Failed)
+}
+
+/// Writes bytes of a remote file to stdout as they are (in non-interactive mode).
+fn write_to_stdout(content: &[u8]) -> Result<(), QuickTransferError> {
+    let mut stdout = io::stdout();
+    stdout
+        .write_all(content)
+        .and_then(|_| stdout.flush())
+        .map_err(|_| QuickTransferError::Stdout)
+}
+
+/// Prints a (textual) fragment of a remote file, ending it with a newline if needed.
+fn print_file_fragment(
+    fragment: &FileFragment,
+    output: &mut impl Write,
+) -> Result<(), QuickTransferError> {
+    let content = String::from_utf8_lossy(&fragment.content);
+    write!(output, "{}", content).map_err(|_| QuickTransferError::Stdout)?;
+    if !content.is_empty() && !content.ends_with('\n') {
+        writeln!(output).map_err(|_| QuickTransferError::Stdout)?;
+    }
+
+    Ok(())
+}
+
+/// Serves a `find` command typed by user.
+async fn serve_find_command(
+    input: &str,
+    writer: &mut impl Write,
+    agent: &mut CommunicationAgent<'_>,
+    rl: Option<&mut Readline>,
+) -> Result<CommandStatus, QuickTransferError> {
+    let (_, arguments) = split_first_argument(input);
+    let Some(query) = parse_find_query(arguments) else {
+        writeln!(
+            writer,
+            "{}",
+            "Usage: `find [-type f|d|l] [-size [+|-]<size>[k|M|G]] [-mtime [+|-]<days>] [-maxdepth <depth>] [-limit <matches>] <pattern>`"
+                .red(),
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Failed);
+    };
+
+    let mut matches = 0;
+    let find_end = agent
+        .find_remote(
+            &query,
+            |found| {
+                matches += 1;
+                if found.file_type == FileType::Directory {
+                    writeln!(writer, "{}", found.path.bright_blue())
+                } else {
+                    writeln!(writer, "{}", found.path)
+                }
+                .map_err(|_| QuickTransferError::Stdout)
+            },
+            interrupted(rl),
+        )
+        .await?;
+
+    match find_end {
+        FindEnd::Completed if matches == 0 => {
+            writeln!(writer, "No matches found.").map_err(|_| QuickTransferError::Stdout)?;
+        }
+        FindEnd::Completed => {}
+        FindEnd::LimitReached => {
+            writeln!(
+                writer,
+                "{}",
+                format!("(Search stopped after {} matches.)", matches).yellow(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        FindEnd::Cancelled => {
+            writeln!(writer, "{}", "Search cancelled.".yellow())
+                .map_err(|_| QuickTransferError::Stdout)?;
+
+            return Ok(CommandStatus::Failed);
+        }
+        FindEnd::ErrorReading => {
+            writeln!(
+                writer,
+                "{}",
+                "Error: An error in reading contents of the directory occurred.".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+
+            return Ok(CommandStatus::Failed);
+        }
+    }
+
+    Ok(CommandStatus::Succeeded)
+}
+
+/// Parses arguments of a `find` command. Sizes and times with `+` are lower bounds (larger, older),
+/// with `-` upper bounds (smaller, newer) and exact values otherwise.
+fn parse_find_query(mut arguments: &str) -> Option<FindQuery> {
+    let mut query = FindQuery {
+        name_pattern: String::new(),
+        file_type: None,
+        min_size: None,
+        max_size: None,
+        modified_after: None,
+        modified_before: None,
+        max_depth: None,
+        limit: None,
+    };
+
+    loop {
+        let (option, rest) = split_first_argument(arguments);
+        if !option.starts_with('-') {
+            break;
+        }
+        let (value, rest) = split_first_argument(rest);
+        arguments = rest;
+
+        let (sign, number) = match value.split_at_checked(1) {
+            Some(("+", number)) => (Some('+'), number),
+            Some(("-", number)) => (Some('-'), number),
+            _ => (None, value),
+        };
+
+        match option {
+            "-type" => {
+                query.file_type = Some(match value {
+                    "f" => FileType::File,
+                    "d" => FileType::Directory,
+                    "l" => FileType::Symlink,
+                    _ => return None,
+                });
+            }
+            "-size" => {
+                let (number, multiplier) =
+                    match number.split_at_checked(number.len().checked_sub(1)?) {
+                        Some((number, "k")) => (number, 1 << 10),
+                        Some((number, "M")) => (number, 1 << 20),
+                        Some((number, "G")) => (number, 1 << 30),
+                        _ => (number, 1),
+                    };
+                let size = number.parse::<u64>().ok()?.checked_mul(multiplier)?;
+
+                match sign {
+                    Some('+') => query.min_size = Some(size),
+                    Some('-') => query.max_size = Some(size),
+                    _ => (query.min_size, query.max_size) = (Some(size), Some(size)),
+                }
+            }
+            "-mtime" => {
+                let days = number.parse::<i64>().ok()?;
+                let now = Local::now().timestamp();
+                let (newer, older) = (now - days * 24 * 60 * 60, now - (days + 1) * 24 * 60 * 60);
+
+                match sign {
+                    Some('+') => query.modified_before = Some(older),
+                    Some('-') => query.modified_after = Some(newer),
+                    _ => (query.modified_after, query.modified_before) = (Some(older), Some(newer)),
+                }
+            }
+            "-maxdepth" => query.max_depth = Some(value.parse().ok().filter(|depth| *depth > 0)?),
+            "-limit" => query.limit = Some(value.parse().ok().filter(|limit| *limit > 0)?),
+            _ => return None,
+        }
+    }
+
+    if arguments.is_empty() || arguments.contains(char::is_whitespace) {
+        return None;
+    }
+    query.name_pattern = String::from(arguments);
+
+    Some(query)
+}
+
+/// Serves a `du` command typed by user.
+async fn serve_du_command(
+    input: &str,
+    writer: &mut impl Write,
+    agent: &mut CommunicationAgent<'_>,
+) -> Result<CommandStatus, QuickTransferError> {
+    let (_, mut path) = split_first_argument(input);
+    let mut summarize = false;
+    if let ("-s", rest) = split_first_argument(path) {
+        summarize = true;
+        path = rest;
+    }
+
+    let displayed_path = if path.is_empty() { "." } else { path };
+    match agent.disk_usage_remote(path, summarize).await? {
+        DuAnswer::FileDirDoesNotExist => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: File/directory `".red(),
+                displayed_path.red(),
+                "` does not exist!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        DuAnswer::IllegalFileDir => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: You don't have access to file/directory `".red(),
+                displayed_path.red(),
+                "`!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        DuAnswer::ErrorReading => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: An error computing disk usage of `".red(),
+                displayed_path.red(),
+                "` has occurred.".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        DuAnswer::Success(usages) => {
+            let sizes: Vec<String> = usages.iter().map(|usage| format_size(usage.size)).collect();
+            let size_width = sizes.iter().map(String::len).max().unwrap_or(0);
+
+            for (usage, size) in usages.iter().zip(sizes) {
+                let files = if usage.files == 1 { "file" } else { "files" };
+                writeln!(
+                    writer,
+                    "{:>width$}  {:>8} {:<5}  {}",
+                    size,
+                    usage.files,
+                    files,
+                    usage.path,
+                    width = size_width,
+                )
+                .map_err(|_| QuickTransferError::Stdout)?;
+            }
+
+            return Ok(CommandStatus::Succeeded);
+        }
+    }
+
+    Ok(CommandStatus::Failed)
+}
+
+/// Serves a `df` command typed by user.
+async fn serve_df_command(
+    input_splitted: &mut SplitWhitespace<'_>,
+    writer: &mut impl Write,
+    agent: &mut CommunicationAgent<'_>,
+) -> Result<CommandStatus, QuickTransferError> {
+    if input_splitted.next().is_some() {
+        writeln!(writer, "{}", "Usage: `df`".red()).map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Failed);
+    }
+
+    match agent.disk_space_remote().await? {
+        DfAnswer::ErrorReading => {
+            writeln!(
+                writer,
+                "{}",
+                "Error: An error reading free space on server has occurred.".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        DfAnswer::Success(space) => {
+            let used = space.total.saturating_sub(space.free);
+            let used_percent = (used * 100).checked_div(space.total).unwrap_or(0);
+
+            writeln!(writer, "Filesystem holding the server root directory:")
+                .map_err(|_| QuickTransferError::Stdout)?;
+            writeln!(writer, "  Total:      {}", format_size(space.total))
+                .map_err(|_| QuickTransferError::Stdout)?;
+            writeln!(
+                writer,
+                "  Used:       {} ({}%)",
+                format_size(used),
+                used_percent
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+            writeln!(writer, "  Available:  {}", format_size(space.available))
+                .map_err(|_| QuickTransferError::Stdout)?;
+
+            return Ok(CommandStatus::Succeeded);
+        }
+    }
+
+    Ok(CommandStatus::Failed)
+}
+
+/// Serves a `checksum` command typed by user.
+async fn serve_checksum_command(
+    input: &str,
+    writer: &mut impl Write,
+    agent: &mut CommunicationAgent<'_>,
+    mut rl: Option<&mut Readline>,
+) -> Result<CommandStatus, QuickTransferError> {
+    let (_, mut arguments) = split_first_argument(input);
+    let mut algorithm = Some(ChecksumAlgorithm::Sha256);
+    if let ("--algo", rest) = split_first_argument(arguments) {
+        let (name, rest) = split_first_argument(rest);
+        algorithm = ChecksumAlgorithm::from_name(name);
+        arguments = rest;
+    }
+    let (file_name, rest) = split_first_argument(arguments);
+    let (local_path, rest) = split_first_argument(rest);
+
+    let (Some(algorithm), false, true) = (algorithm, file_name.is_empty(), rest.is_empty()) else {
+        writeln!(
+            writer,
+            "{}",
+            "Usage: `checksum [--algo sha256|blake3] <remote_path> [<local_path>]`".red(),
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Failed);
+    };
+
+    writeln!(
+        writer,
+        "Computing checksum of `{}` on server (press Ctrl-C to cancel)...",
+        file_name
+    )
+    .map_err(|_| QuickTransferError::Stdout)?;
+    if let Some(rl) = &mut rl {
+        rl.flush().map_err(|_| QuickTransferError::Stdout)?;
+    }
+
+    let checksum_answer = agent
+        .checksum_remote(file_name, algorithm, interrupted(rl.as_deref_mut()))
+        .await?;
+    let remote_digest = match checksum_answer {
+        ChecksumAnswer::FileDoesNotExist => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: File `".red(),
+                file_name.red(),
+                "` does not exist!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+
+            return Ok(CommandStatus::Failed);
+        }
+        ChecksumAnswer::IllegalFile => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: You don't have access to file `".red(),
+                file_name.red(),
+                "`!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+
+            return Ok(CommandStatus::Failed);
+        }
+        ChecksumAnswer::ErrorReading => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: An error reading file `".red(),
+                file_name.red(),
+                "` has occurred.".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+
+            return Ok(CommandStatus::Failed);
+        }
+        ChecksumAnswer::Cancelled => {
+            writeln!(writer, "{}", "Computing checksum cancelled.".yellow())
+                .map_err(|_| QuickTransferError::Stdout)?;
+
+            return Ok(CommandStatus::Failed);
+        }
+        ChecksumAnswer::Success(digest) => digest,
+    };
+
+    writeln!(
+        writer,
+        "{}  {} ({})",
+        to_hex(&remote_digest),
+        file_name,
+        algorithm.name()
+    )
+    .map_err(|_| QuickTransferError::Stdout)?;
+
+    if local_path.is_empty() {
+        return Ok(CommandStatus::Succeeded);
+    }
+
+    let (cancelled, mut local_digest) = spawn_file_digest(PathBuf::from(local_path), algorithm);
+    let local_digest = tokio::select! {
+        local_digest = &mut local_digest => local_digest,
+        _ = interrupted(rl) => {
+            cancelled.store(true, Ordering::Relaxed);
+            local_digest.await
+        }
+    };
+
+    match local_digest {
+        Ok(Ok(local_digest)) if local_digest == remote_digest => {
+            writeln!(
+                writer,
+                "{}",
+                format!("Local file `{}`: match", local_path).green()
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+
+            return Ok(CommandStatus::Succeeded);
+        }
+        Ok(Ok(_)) => writeln!(
+            writer,
+            "{}",
+            format!("Local file `{}`: differs", local_path).red()
+        ),
+        Ok(Err(error)) if error.kind() == ErrorKind::Interrupted => {
+            writeln!(writer, "{}", "Computing checksum cancelled.".yellow())
+        }
+        _ => writeln!(
+            writer,
+            "{}{}{}",
+            "Error: An error reading local file `".red(),
+            local_path.red(),
+            "` has occurred.".red(),
+        ),
+    }
+    .map_err(|_| QuickTransferError::Stdout)?;
+
+    Ok(CommandStatus::Failed)
+}
+
+/// Completes when user presses Ctrl-C (in interactive mode Ctrl-C is read by `rl`).
+async fn interrupted(rl: Option<&mut Readline>) {
+    match rl {
+        Some(rl) => loop {
+            match rl.readline().await {
+                Ok(ReadlineEvent::Interrupted) => return,
+                Ok(_) => {}
+                Err(_) => std::future::pending().await,
+            }
+        },
+        None => {
+            if tokio::signal::ctrl_c().await.is_err() {
+                std::future::pending().await
+            }
+        }
+    }
+}
+
+/// Serves a `cp` command typed by user.
+async fn serve_cp_command(
+    input_splitted: &mut SplitWhitespace<'_>,
+    writer: &mut impl Write,
+    agent: &mut CommunicationAgent<'_>,
+    mut rl: Option<&mut Readline>,
+) -> Result<CommandStatus, QuickTransferError> {
+    let mut flags = 0;
+    let mut paths = vec![];
+    for argument in input_splitted {
+        match argument.strip_prefix('-') {
+            Some(options) if paths.is_empty() && !options.is_empty() => {
+                for option in options.chars() {
+                    flags |= match option {
+                        'r' => COPY_RECURSIVE,
+                        'f' => COPY_OVERWRITE,
+                        _ => u64::MAX,
+                    };
+                }
+            }
+            _ => paths.push(argument),
+        }
+    }
+
+    let (&[source, destination], true) =
+        (&paths[..], flags & !(COPY_RECURSIVE | COPY_OVERWRITE) == 0)
+    else {
+        writeln!(
+            writer,
+            "{}",
+            "Usage: `cp [-r] [-f] <source_path> <destination_path>`. `-r` copies directories, `-f` overwrites existing files."
+                .red(),
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Failed);
+    };
+
+    let copy_answer = agent
+        .copy_remote(source, destination, flags, |progress| {
+            let percent = (progress.copied_bytes * 100)
+                .checked_div(progress.total_bytes)
+                .unwrap_or(100);
+            writeln!(
+                writer,
+                "Copying `{}`... {} of {} ({}%)",
+                source,
+                format_size(progress.copied_bytes),
+                format_size(progress.total_bytes),
+                percent
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+            if let Some(rl) = &mut rl {
+                rl.flush().map_err(|_| QuickTransferError::Stdout)?;
+            }
+
+            Ok(())
+        })
+        .await?;
+
+    let error = match copy_answer {
+        CopyAnswer::Success => {
+            writeln!(
+                writer,
+                "Successfully copied `{}` to `{}`!",
+                source, destination
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+
+            return Ok(CommandStatus::Succeeded);
+        }
+        CopyAnswer::FileDirDoesNotExist => {
+            format!(
+                "Error: `{}` or the parent of `{}` does not exist!",
+                source, destination
+            )
+        }
+        CopyAnswer::IllegalFileDir => {
+            format!(
+                "Error: You don't have access to file/directory `{}`!",
+                source
+            )
+        }
+        CopyAnswer::IllegalDestination => {
+            format!(
+                "Error: You don't have access to destination `{}`!",
+                destination
+            )
+        }
+        CopyAnswer::DestinationExists => format!(
+            "Error: Destination `{}` already exists! Use `-f` to overwrite it.",
+            destination
+        ),
+        CopyAnswer::DestinationInsideSource => {
+            format!("Error: Cannot copy `{}` into itself!", source)
+        }
+        CopyAnswer::IsDirectory => format!(
+            "Error: `{}` is a directory! Use `-r` to copy directories.",
+            source
+        ),
+        CopyAnswer::ErrorCopying => {
+            format!("Error: An error copying `{}` has occurred.", source)
+        }
+        CopyAnswer::NotEnoughSpace => {
+            format!("Error: Not enough space on server to copy `{}`!", source)
+        }
+    };
+    writeln!(writer, "{}", error.red()).map_err(|_| QuickTransferError::Stdout)?;
+
+    Ok(CommandStatus::Failed)
+}
+
+/// Serves a `stat` command typed by user.
+async fn serve_stat_command(
+    input: &str,
+    writer: &mut impl Write,
+    agent: &mut CommunicationAgent<'_>,
+) -> Result<CommandStatus, QuickTransferError> {
+    let (_, file_name) = split_first_argument(input);
+    if file_name.is_empty() {
+        writeln!(
+            writer,
+            "{}",
+            "Usage: `stat <path>`. `<path>` should be the path of a file/directory relative to current view."
+                .red(),
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        return Ok(CommandStatus::Failed);
+    }
+
+    match agent.stat_remote_file(file_name).await? {
+        StatAnswer::FileDirDoesNotExist => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: File/directory `".red(),
+                file_name.red(),
+                "` does not exist!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        StatAnswer::IllegalFileDir => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: You don't have access to file/directory `".red(),
+                file_name.red(),
+                "`!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        StatAnswer::ErrorReadingMetadata => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: An error reading metadata of `".red(),
+                file_name.red(),
+                "` has occurred.".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        StatAnswer::Success(metadata) => {
+            print_file_metadata(&metadata, writer).map_err(|_| QuickTransferError::Stdout)?;
+
+            return Ok(CommandStatus::Succeeded);
+        }
+    }
+
+    Ok(CommandStatus::Failed)
 }
 
-/// Serves an `upload` command typed by user.
-async fn serve_upload_command(
-    input: &str,
-    writer: &mut SharedWriter,
-    agent: &mut CommunicationAgent<'_>,
-    rl: &mut Readline,
-) -> Result<(), QuickTransferError> {
-    let file_name = parse_file_name(input, "upload <file_path>", "<file_path>", writer);
-    let Some(file_name) = file_name else {
-        return Ok(());
+/// Prints metadata of a remote file/directory in a `stat`-like form.
+fn print_file_metadata(metadata: &FileMetadata, writer: &mut impl Write) -> io::Result<()> {
+    match &metadata.symlink_target {
+        Some(target) => writeln!(writer, "  File: {} -> {}", metadata.name, target)?,
+        None => writeln!(writer, "  File: {}", metadata.name)?,
+    }
+
+    let file_type = match metadata.file_type {
+        FileType::File => "regular file",
+        FileType::Directory => "directory",
+        FileType::Symlink => "symbolic link",
+        FileType::Other => "other",
     };
-    let file_path = Path::new(&file_name);
+    writeln!(writer, "  Type: {}", file_type)?;
+    writeln!(
+        writer,
+        "  Size: {} ({})",
+        metadata.size,
+        format_size(metadata.size)
+    )?;
 
-    if !fs::exists(file_path).unwrap() || !file_path.is_file() {
-        writeln!(
+    match metadata.mode {
+        Some(mode) => writeln!(
             writer,
-            "{}{}{}",
-            "Error: File `".red(),
-            file_name.red(),
-            "` does not exist!".red(),
-        )
-        .map_err(|_| QuickTransferError::Stdout)?;
+            "  Mode: {} ({:04o})",
+            permissions_string(metadata.file_type, mode),
+            mode & 0o7777
+        )?,
+        None if metadata.read_only => writeln!(writer, "  Mode: read-only")?,
+        None => writeln!(writer, "  Mode: read-write")?,
+    }
 
-        return Ok(());
+    if let Some(owner) = &metadata.owner {
+        let user_name = owner.user_name.as_deref().unwrap_or("?");
+        let group_name = owner.group_name.as_deref().unwrap_or("?");
+        writeln!(writer, " Owner: {} ({})", user_name, owner.user_id)?;
+        writeln!(writer, " Group: {} ({})", group_name, owner.group_id)?;
     }
 
-    let Ok(opened_file) = File::open(file_path) else {
-        writeln!(
-            writer,
-            "{}{}{}",
-            "Error opening file `".red(),
-            file_name.red(),
-            "`.".red(),
-        )
-        .map_err(|_| QuickTransferError::Stdout)?;
+    writeln!(writer, "Modify: {}", format_time(metadata.modified))?;
+    writeln!(writer, "Access: {}", format_time(metadata.accessed))?;
+    writeln!(writer, " Birth: {}", format_time(metadata.created))?;
 
-        return Ok(());
-    };
+    Ok(())
+}
 
-    let Ok(file_size) = opened_file.metadata() else {
-        writeln!(
-            writer,
-            "{}{}{}",
-            "Error reading size of file `".red(),
-            file_name.red(),
-            "`.".red(),
-        )
-        .map_err(|_| QuickTransferError::Stdout)?;
+/// Formats Unix permission bits like `ls -l` does (e.g. `drwxr-xr-x`).
+fn permissions_string(file_type: FileType, mode: u32) -> String {
+    let mut permissions = String::from(match file_type {
+        FileType::File => '-',
+        FileType::Directory => 'd',
+        FileType::Symlink => 'l',
+        FileType::Other => '?',
+    });
+
+    // (read bit, write bit, execute bit, special bit, special character):
+    let classes = [
+        (0o400, 0o200, 0o100, 0o4000, 's'),
+        (0o040, 0o020, 0o010, 0o2000, 's'),
+        (0o004, 0o002, 0o001, 0o1000, 't'),
+    ];
+    for (read, write, execute, special, special_char) in classes {
+        permissions.push(if mode & read != 0 { 'r' } else { '-' });
+        permissions.push(if mode & write != 0 { 'w' } else { '-' });
+        permissions.push(match (mode & execute != 0, mode & special != 0) {
+            (true, true) => special_char,
+            (false, true) => special_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
 
-        return Ok(());
-    };
-    let file_name_truncated = Path::new(&file_name)
-        .file_name()
-        .map(|string| string.to_str().map(|string| string.to_string()))
-        .unwrap_or(Some(file_name.clone()))
-        .unwrap_or(file_name.clone());
+    permissions
+}
 
-    writeln!(writer, "Uploading file `{}`...", file_name)
-        .map_err(|_| QuickTransferError::Stdout)?;
-    rl.flush().map_err(|_| QuickTransferError::Stdout)?;
-    agent
-        .send_upload(
-            opened_file,
-            file_size.len(),
-            &file_name_truncated,
-            file_path,
-        )
-        .await?;
+/// Formats permissions of a directory position, also when the server does not send Unix mode.
+fn mode_string(metadata: &FileMetadata) -> String {
+    match metadata.mode {
+        Some(mode) => permissions_string(metadata.file_type, mode),
+        None if metadata.read_only => permissions_string(metadata.file_type, 0o444),
+        None => permissions_string(metadata.file_type, 0o666),
+    }
+}
 
-    let message = agent.receive_tcp(false).await?;
-    let message = agent.read_message_header_check(&message, MESSAGE_UPLOAD_RESULT)?;
-    let upload_result = agent.read_answer(message)?;
+/// Formats a size in bytes in a human-readable form (e.g. `1.5 KiB`).
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
 
-    match upload_result {
-        UploadResult::Fail(fail) => {
-            match fail {
-                FileFail::ErrorCreatingFile => {
-                    writeln!(writer, "Uploading file `{}` failed. An error creating the file on server occurred.", file_name).map_err(|_| QuickTransferError::Stdout)?;
-                }
-                _ => {
-                    writeln!(writer, "Uploading file `{}` failed.", file_name)
-                        .map_err(|_| QuickTransferError::Stdout)?;
-                }
-            }
-        }
-        UploadResult::Success => {
-            writeln!(writer, "Successfully uploaded file `{}`!", file_name)
-                .map_err(|_| QuickTransferError::Stdout)?;
-        }
+    if size < 1024 {
+        return format!("{} B", size);
     }
 
-    Ok(())
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Formats a time given in seconds since the Unix epoch in the local time zone.
+fn format_time(time: Option<i64>) -> String {
+    time.and_then(|time| DateTime::from_timestamp(time, 0))
+        .map(|time| {
+            time.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S %z")
+                .to_string()
+        })
+        .unwrap_or_else(|| String::from("-"))
 }
 
 /// Serves a `mkdir` command typed by user.
 async fn serve_mkdir_command(
     input: &str,
-    writer: &mut SharedWriter,
+    writer: &mut impl Write,
     agent: &mut CommunicationAgent<'_>,
-) -> Result<(), QuickTransferError> {
+) -> Result<CommandStatus, QuickTransferError> {
     let directory_name = input.split_once(char::is_whitespace);
     if directory_name.is_none() {
         writeln!(
@@ -559,7 +2157,7 @@ async fn serve_mkdir_command(
         )
         .map_err(|_| QuickTransferError::Stdout)?;
 
-        return Ok(());
+        return Ok(CommandStatus::Failed);
     }
 
     let directory_name = String::from(directory_name.unwrap().1);
@@ -573,7 +2171,7 @@ async fn serve_mkdir_command(
         )
         .map_err(|_| QuickTransferError::Stdout)?;
 
-        return Ok(());
+        return Ok(CommandStatus::Failed);
     }
 
     agent.send_mkdir(&directory_name).await?;
@@ -628,27 +2226,36 @@ async fn serve_mkdir_command(
             let dir_description = agent.read_answer(message)?;
 
             if let MessageDirectoryContents::Success(dir_description) = dir_description {
-                print_directory_contents(&dir_description, writer)?;
+                print_directory_contents(&dir_description, false, writer)?;
             }
+
+            return Ok(CommandStatus::Succeeded);
         }
     }
 
-    Ok(())
+    Ok(CommandStatus::Failed)
 }
 
 /// Serves a `mv` command typed by user.
 async fn serve_mv_command(
     input: &str,
-    writer: &mut SharedWriter,
+    writer: &mut impl Write,
     agent: &mut CommunicationAgent<'_>,
-) -> Result<(), QuickTransferError> {
+) -> Result<CommandStatus, QuickTransferError> {
+    let (_, arguments) = split_first_argument(input);
+    let (flags, arguments) = match split_first_argument(arguments) {
+        ("-f", rest) => (RENAME_OVERWRITE, rest),
+        _ => (0, arguments),
+    };
     let Some((file_dir_name, new_name)) =
-        parse_file_dir_name_and_name(input, "mv <file_dir_path> <new_name>", writer)
+        parse_file_dir_name_and_name(arguments, "mv [-f] <file_dir_path> <new_path>", writer)
     else {
-        return Ok(());
+        return Ok(CommandStatus::Failed);
     };
 
-    agent.send_rename_request(&file_dir_name, &new_name).await?;
+    agent
+        .send_rename_request(&file_dir_name, &new_name, flags)
+        .await?;
 
     let message = agent.receive_tcp(false).await?;
     let message = agent.read_message_header_check(&message, MESSAGE_RENAME_ANSWER)?;
@@ -685,10 +2292,50 @@ async fn serve_mv_command(
             )
             .map_err(|_| QuickTransferError::Stdout)?;
         }
+        RenameAnswer::DestinationDoesNotExist => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: The directory of `".red(),
+                new_name.red(),
+                "` does not exist!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        RenameAnswer::IllegalDestination => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: You don't have access to destination `".red(),
+                new_name.red(),
+                "`!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        RenameAnswer::DestinationExists => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: Destination `".red(),
+                new_name.red(),
+                "` already exists! Use `-f` to overwrite it.".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        RenameAnswer::DestinationInsideSource => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: Cannot move `".red(),
+                file_dir_name.red(),
+                "` into itself!".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
         RenameAnswer::Success => {
             writeln!(
                 writer,
-                "Successfully renamed `{}` to `{}`.",
+                "Successfully moved `{}` to `{}`.",
                 file_dir_name, new_name
             )
             .map_err(|_| QuickTransferError::Stdout)?;
@@ -700,30 +2347,66 @@ async fn serve_mv_command(
             let dir_description = agent.read_answer(message)?;
 
             if let MessageDirectoryContents::Success(dir_description) = dir_description {
-                print_directory_contents(&dir_description, writer)?;
+                print_directory_contents(&dir_description, false, writer)?;
             }
+
+            return Ok(CommandStatus::Succeeded);
         }
     }
 
-    Ok(())
+    Ok(CommandStatus::Failed)
 }
 
 /// Serves a `rm` command typed by user.
 async fn serve_rm_command(
     input: &str,
-    writer: &mut SharedWriter,
+    writer: &mut impl Write,
     agent: &mut CommunicationAgent<'_>,
-) -> Result<(), QuickTransferError> {
-    let file_dir_name = parse_file_name(input, "rm <file_dir_path>", "<file_dir_path>", writer);
-    let Some(file_dir_name) = file_dir_name else {
-        return Ok(());
+    rl: Option<&mut Readline>,
+) -> Result<CommandStatus, QuickTransferError> {
+    let (_, arguments) = split_first_argument(input);
+    let (flags, file_dir_name) = match split_first_argument(arguments) {
+        ("-r", rest) => (REMOVE_RECURSIVE, rest),
+        _ => (0, arguments),
     };
+    if file_dir_name.is_empty() {
+        writeln!(writer, "{}", "Usage: `rm [-r] <file_dir_path>`".red())
+            .map_err(|_| QuickTransferError::Stdout)?;
 
-    agent.send_remove_request(&file_dir_name).await?;
+        return Ok(CommandStatus::Failed);
+    }
 
-    let message = agent.receive_tcp(false).await?;
-    let message = agent.read_message_header_check(&message, MESSAGE_REMOVE_ANSWER)?;
-    let remove_answer = agent.read_answer(message)?;
+    // Before a recursive removal, the user has to confirm what is going to be removed:
+    let preview_flags = if flags & REMOVE_RECURSIVE != 0 {
+        REMOVE_PREVIEW
+    } else {
+        0
+    };
+    let mut remove_answer =
+        receive_remove_answer(agent, file_dir_name, flags | preview_flags).await?;
+    if let RemoveAnswer::Preview(usage) = &remove_answer {
+        writeln!(
+            writer,
+            "{}",
+            format!(
+                "`{}` holds {} file(s) ({}), which will be removed permanently. Continue? [y/N]",
+                file_dir_name,
+                usage.files,
+                format_size(usage.size)
+            )
+            .yellow()
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        if !confirmed(rl).await? {
+            writeln!(writer, "Removal of `{}` cancelled.", file_dir_name)
+                .map_err(|_| QuickTransferError::Stdout)?;
+
+            return Ok(CommandStatus::Failed);
+        }
+
+        remove_answer = receive_remove_answer(agent, file_dir_name, flags).await?;
+    }
 
     match remove_answer {
         RemoveAnswer::FileDirDoesNotExist => {
@@ -762,9 +2445,30 @@ async fn serve_rm_command(
                 "{}{}{}",
                 "Error: Directory `".red(),
                 file_dir_name.red(),
-                "` is not empty! For safety reasons, deleting non-empty directories is not allowed.".red(),
+                "` is not empty! Use `-r` to remove it with its contents.".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        RemoveAnswer::RecursiveRemoveNotAllowed => {
+            writeln!(
+                writer,
+                "{}",
+                "Error: The server does not allow recursive removal (it has to be started with `--allow-recursive-delete`).".red(),
             ).map_err(|_| QuickTransferError::Stdout)?;
         }
+        RemoveAnswer::ContainsCurrentDirectory => {
+            writeln!(
+                writer,
+                "{}{}{}",
+                "Error: `".red(),
+                file_dir_name.red(),
+                "` contains current directory! Change directory first.".red(),
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+        }
+        RemoveAnswer::Preview(_) => {
+            return Err(QuickTransferError::SentInvalidData(ProgramRole::Client));
+        }
         RemoveAnswer::Success => {
             writeln!(writer, "Successfully removed `{}`.", file_dir_name,)
                 .map_err(|_| QuickTransferError::Stdout)?;
@@ -776,12 +2480,57 @@ async fn serve_rm_command(
             let dir_description = agent.read_answer(message)?;
 
             if let MessageDirectoryContents::Success(dir_description) = dir_description {
-                print_directory_contents(&dir_description, writer)?;
+                print_directory_contents(&dir_description, false, writer)?;
             }
+
+            return Ok(CommandStatus::Succeeded);
         }
     }
 
-    Ok(())
+    Ok(CommandStatus::Failed)
+}
+
+/// Sends a remove request and receives the answer (removing a directory recursively may take
+/// long, so no timeout is set).
+async fn receive_remove_answer(
+    agent: &mut CommunicationAgent<'_>,
+    file_dir_name: &str,
+    flags: u64,
+) -> Result<RemoveAnswer, QuickTransferError> {
+    agent.send_remove_request(file_dir_name, flags).await?;
+
+    let message = agent.receive_tcp(flags & REMOVE_RECURSIVE != 0).await?;
+    let message = agent.read_message_header_check(&message, MESSAGE_REMOVE_ANSWER)?;
+
+    agent.read_answer(message)
+}
+
+/// Reads user's answer to a yes/no question (from stdin in non-interactive mode).
+/// Anything other than `y`/`yes` means no.
+async fn confirmed(rl: Option<&mut Readline>) -> Result<bool, QuickTransferError> {
+    let answer = match rl {
+        Some(rl) => match rl.readline().await {
+            Ok(ReadlineEvent::Line(line)) => line,
+            Ok(_) => String::new(),
+            Err(error) => {
+                return Err(QuickTransferError::ReadLine {
+                    error: error.to_string(),
+                })
+            }
+        },
+        None => {
+            let mut line = String::new();
+            BufReader::new(tokio::io::stdin())
+                .read_line(&mut line)
+                .await
+                .map_err(|error| QuickTransferError::ReadLine {
+                    error: error.to_string(),
+                })?;
+            line
+        }
+    };
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
 }
 
 /// Connects client to a server.
@@ -807,29 +2556,199 @@ async fn connect_to_server(
     Ok(stream)
 }
 
+/// Starts TLS on the connection (if the client uses TLS). The server's certificate has to match
+/// the pinned fingerprint (given with `--fingerprint` or remembered on the first connection).
+async fn start_transport(
+    program_options: &ProgramOptions,
+    stream: TcpStream,
+) -> Result<Box<dyn Transport>, QuickTransferError> {
+    if !program_options.tls {
+        return Ok(Box::new(stream));
+    }
+
+    let server = format!(
+        "[{}]:{}",
+        program_options.server_ip_address, program_options.port
+    );
+    let tls_directory = &program_options.tls_directory;
+    let pinned = program_options
+        .tls_fingerprint
+        .or_else(|| known_fingerprint(tls_directory, &server));
+
+    let (stream, server_fingerprint) = time::timeout(
+        Duration::from_secs(program_options.timeout.into()),
+        tls::connect(stream, &program_options.server_ip_address, pinned),
+    )
+    .await
+    .map_err(|_| QuickTransferError::TlsHandshake)?
+    .map_err(|error| {
+        if is_certificate_mismatch(&error) {
+            QuickTransferError::CertificateMismatch {
+                server: server.clone(),
+                known_servers: tls_directory.join(KNOWN_SERVERS_FILE).display().to_string(),
+            }
+        } else {
+            QuickTransferError::TlsHandshake
+        }
+    })?;
+
+    if pinned.is_none() {
+        // Trust on first use:
+        remember_fingerprint(tls_directory, &server, &server_fingerprint).map_err(|_| {
+            QuickTransferError::WritingFile {
+                file_path: tls_directory.join(KNOWN_SERVERS_FILE).display().to_string(),
+            }
+        })?;
+        eprintln!(
+            "{}",
+            format!(
+                "The certificate of server `{}` was trusted on first use (fingerprint {}).",
+                server,
+                to_hex(&server_fingerprint)
+            )
+            .yellow()
+        );
+    }
+
+    Ok(Box::new(stream))
+}
+
+/// Prints directory contents in one line. Hidden positions (starting with a dot) are skipped
+/// unless `show_hidden` is set. Plain listings do not tell symbolic links apart (this needs
+/// metadata of the positions).
 fn print_directory_contents(
     dir_description: &DirectoryContents,
-    writer: &mut SharedWriter,
+    show_hidden: bool,
+    writer: &mut impl Write,
+) -> Result<(), QuickTransferError> {
+    print_directory_location(&dir_description.location, writer)?;
+
+    let positions = dir_description
+        .positions
+        .iter()
+        .filter(|position| show_hidden || !position.name.starts_with('.'))
+        .map(|position| (position.name.as_str(), position.is_directory, false));
+    print_directory_positions(positions, writer)
+}
+
+/// Prints directory contents with metadata, sorted and filtered according to `ls` options.
+fn print_detailed_directory_contents(
+    mut dir_description: DetailedDirectoryContents,
+    options: &LsOptions,
+    writer: &mut impl Write,
+) -> Result<(), QuickTransferError> {
+    print_directory_location(&dir_description.location, writer)?;
+
+    let positions = &mut dir_description.positions;
+    if !options.all {
+        positions.retain(|position| !position.name.starts_with('.'));
+    }
+
+    positions.sort_by(|a, b| a.name.cmp(&b.name));
+    if options.by_size {
+        positions.sort_by_key(|position| std::cmp::Reverse(position.size));
+    } else if options.by_time {
+        positions.sort_by_key(|position| std::cmp::Reverse(position.modified));
+    }
+    if options.reverse {
+        positions.reverse();
+    }
+
+    if !options.long {
+        let positions = positions.iter().map(|position| {
+            (
+                position.name.as_str(),
+                position.file_type == FileType::Directory,
+                position.file_type == FileType::Symlink,
+            )
+        });
+        return print_directory_positions(positions, writer);
+    }
+
+    if positions.is_empty() {
+        writeln!(writer, "(empty)").map_err(|_| QuickTransferError::Stdout)?;
+    }
+
+    let sizes: Vec<String> = positions
+        .iter()
+        .map(|position| format_size(position.size))
+        .collect();
+    let size_width = sizes.iter().map(String::len).max().unwrap_or(0);
+
+    for (position, size) in positions.iter().zip(sizes) {
+        let modified = position
+            .modified
+            .and_then(|time| DateTime::from_timestamp(time, 0))
+            .map(|time| {
+                time.with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_else(|| String::from("-"));
+
+        write!(
+            writer,
+            "{}  {:>width$}  {:<16}  ",
+            mode_string(position),
+            size,
+            modified,
+            width = size_width,
+        )
+        .map_err(|_| QuickTransferError::Stdout)?;
+
+        match (&position.file_type, &position.symlink_target) {
+            (FileType::Directory, _) => writeln!(writer, "{}", position.name.bright_blue()),
+            (FileType::Symlink, Some(target)) => {
+                writeln!(writer, "{} -> {}", position.name.cyan(), target)
+            }
+            (FileType::Symlink, None) => writeln!(writer, "{}", position.name.cyan()),
+            _ => writeln!(writer, "{}", position.name.white()),
+        }
+        .map_err(|_| QuickTransferError::Stdout)?;
+    }
+
+    Ok(())
+}
+
+/// Prints a header of a directory listing.
+fn print_directory_location(
+    location: &str,
+    writer: &mut impl Write,
 ) -> Result<(), QuickTransferError> {
     writeln!(
         writer,
         "{}{}{}",
         "Displaying contents of ".magenta(),
-        dir_description.location.on_magenta().white(),
+        location.on_magenta().white(),
         ":".magenta()
     )
-    .map_err(|_| QuickTransferError::Stdout)?;
+    .map_err(|_| QuickTransferError::Stdout)
+}
 
-    for position in &dir_description.positions {
-        if position.is_directory {
-            write!(writer, "{}    ", position.name.bright_blue())
-                .map_err(|_| QuickTransferError::Stdout)?;
+/// Prints names of directory positions (given with the information whether they are directories
+/// and whether they are symbolic links) in one line. Symbolic links are marked with `@`.
+fn print_directory_positions<'a>(
+    positions: impl Iterator<Item = (&'a str, bool, bool)>,
+    writer: &mut impl Write,
+) -> Result<(), QuickTransferError> {
+    let mut is_empty = true;
+    for (name, is_directory, is_symlink) in positions {
+        is_empty = false;
+        if is_symlink {
+            let name = format!("{}@", name);
+            let name = if is_directory {
+                name.bright_cyan()
+            } else {
+                name.cyan()
+            };
+            write!(writer, "{}    ", name).map_err(|_| QuickTransferError::Stdout)?;
+        } else if is_directory {
+            write!(writer, "{}    ", name.bright_blue()).map_err(|_| QuickTransferError::Stdout)?;
         } else {
-            write!(writer, "{}    ", position.name.white())
-                .map_err(|_| QuickTransferError::Stdout)?;
+            write!(writer, "{}    ", name.white()).map_err(|_| QuickTransferError::Stdout)?;
         }
     }
-    if dir_description.positions.is_empty() {
+    if is_empty {
         write!(writer, "(empty)").map_err(|_| QuickTransferError::Stdout)?;
     }
     writeln!(writer).map_err(|_| QuickTransferError::Stdout)?;
@@ -842,7 +2761,7 @@ fn parse_file_name(
     input: &str,
     command: &str,
     file_path_name: &str,
-    writer: &mut SharedWriter,
+    writer: &mut impl Write,
 ) -> Option<String> {
     let file_name = input.split_once(char::is_whitespace);
     if file_name.is_none() {
@@ -876,18 +2795,53 @@ fn parse_file_name(
     Some(file_name)
 }
 
-/// Parses file name and second argument returning error, if needed.
+/// Splits off a trailing `-` (meaning stdout) from a path given to a download command.
+/// Returns `None` (after printing an error) if stdout cannot be used in interactive mode.
+fn parse_stdout_destination(
+    file_name: &str,
+    interactive: bool,
+    writer: &mut impl Write,
+) -> Option<(String, bool)> {
+    let Some(file_name) = file_name.strip_suffix(" -") else {
+        return Some((String::from(file_name), false));
+    };
+
+    if interactive {
+        let _ = writeln!(
+            writer,
+            "{}",
+            "Error: Downloading to stdout (`-`) is possible only with a command given in program arguments."
+                .red(),
+        );
+
+        return None;
+    }
+
+    Some((String::from(file_name.trim_end()), true))
+}
+
+/// Splits off the first whitespace-separated argument (returns it and the rest of the input).
+fn split_first_argument(input: &str) -> (&str, &str) {
+    let (argument, rest) = input
+        .trim_start()
+        .split_once(char::is_whitespace)
+        .unwrap_or((input.trim_start(), ""));
+
+    (argument, rest.trim_start())
+}
+
+/// Parses file name and second argument (from arguments of a command) returning error, if needed.
 fn parse_file_dir_name_and_name(
     input: &str,
     command: &str,
-    writer: &mut SharedWriter,
+    writer: &mut impl Write,
 ) -> Option<(String, String)> {
-    let mut file_name = input.splitn(3, char::is_whitespace);
-    if file_name.next().is_none() {
-        let _ = writeln!(writer, "{}{}", "Usage: `".red(), command.red());
+    if input.is_empty() {
+        let _ = writeln!(writer, "{}{}{}", "Usage: `".red(), command.red(), "`".red());
 
         return None;
     }
+    let mut file_name = input.splitn(2, char::is_whitespace);
 
     let file_name1 = String::from(file_name.next().unwrap_or(""));
     let file_name2 = String::from(file_name.next().unwrap_or(""));
@@ -904,7 +2858,7 @@ fn parse_file_dir_name_and_name(
     }
 
     if file_name2.is_empty() {
-        let _ = writeln!(writer, "{}", "Note: `new_name` cannot be empty. ".red());
+        let _ = writeln!(writer, "{}", "Note: `new_path` cannot be empty. ".red());
 
         return None;
     }
@@ -922,26 +2876,85 @@ fn preprint_user_help(help_msg: &mut String) {
         "                                 directory in which the server is being run).\n",
     );
 
-    help_msg.push_str("  ls                             Display current directory contents.\n");
+    help_msg
+        .push_str("  ls [-l] [-a] [-t] [-S] [-r]    Display current directory contents. `-l`:\n");
+    help_msg
+        .push_str("                                 long listing (permissions, size, mtime),\n");
+    help_msg
+        .push_str("                                 `-a`: show hidden files, `-t`/`-S`: sort\n");
+    help_msg
+        .push_str("                                 by modification time/size, `-r`: reverse.\n");
 
     help_msg.push_str("  download <file_path>           Download the file from `file_path`\n");
     help_msg.push_str("                                 (relative to current view) to current\n");
     help_msg.push_str("                                 directory (i.e. on which QuickTransfer\n");
     help_msg.push_str("                                 has been run). If the file exists, it\n");
     help_msg.push_str("                                 will be overwritten.\n");
+    help_msg.push_str("  download --tar [-z] [-x] <directory_path>\n");
+    help_msg.push_str("                                 Download the directory from\n");
+    help_msg.push_str("                                 `directory_path` as a tar archive\n");
+    help_msg.push_str("                                 (`-z`: gzip-compressed) saved as\n");
+    help_msg.push_str("                                 `<name>.tar` or, with `-x`, unpacked\n");
+    help_msg.push_str("                                 into current directory as it arrives.\n");
 
     help_msg
-        .push_str("  upload <file_path>             Upload the file from `file_path` (relative\n");
+        .push_str("  upload [-f] <file_path>        Upload the file from `file_path` (relative\n");
     help_msg.push_str("                                 to current directory, i.e. on which\n");
     help_msg
         .push_str("                                 QuickTransfer has been run) to directory\n");
     help_msg.push_str("                                 in current view (overrides files). If\n");
     help_msg
         .push_str("                                 the file exists, it will be overwritten.\n");
+    help_msg.push_str("                                 An identical file (same size and hash)\n");
+    help_msg.push_str("                                 is not sent again unless `-f` is given.\n");
+    help_msg.push_str("  cat <file_path>                Display the file from `file_path` (its\n");
+    help_msg.push_str("                                 first 64 KiB).\n");
+    help_msg.push_str(
+        "  head [-n <lines>] <file_path>  Display first lines of the file (default 10).\n",
+    );
+    help_msg.push_str("  tail [-f] [-n <lines>] <file_path>\n");
+    help_msg.push_str("                                 Display last lines of the file (default\n");
+    help_msg.push_str("                                 10). `-f`: then display lines appended\n");
+    help_msg.push_str("                                 to it until Ctrl-C is pressed.\n");
+    help_msg.push_str("  read <offset> <length> <file_path>\n");
+    help_msg
+        .push_str("                                 Display `length` bytes of the file starting\n");
+    help_msg.push_str("                                 at `offset` (at most 64 KiB).\n");
+    help_msg
+        .push_str("  find [<options>] <pattern>     Search current directory (recursively) for\n");
+    help_msg
+        .push_str("                                 names matching a glob `pattern` (`*`, `?`,\n");
+    help_msg.push_str("                                 `[a-z]`). Options: `-type f|d|l`,\n");
+    help_msg.push_str("                                 `-size [+|-]<size>[k|M|G]`,\n");
+    help_msg
+        .push_str("                                 `-mtime [+|-]<days>`, `-maxdepth <depth>`,\n");
+    help_msg.push_str("                                 `-limit <matches>`.\n");
+    help_msg.push_str("  du [-s] [<path>]               Display sizes (and numbers of files) of\n");
+    help_msg
+        .push_str("                                 positions inside `path` (default: current\n");
+    help_msg.push_str("                                 view) and their total (only the total\n");
+    help_msg.push_str("                                 with `-s`).\n");
+    help_msg.push_str("  df                             Display free/total space on server.\n");
+    help_msg.push_str("  checksum [--algo sha256|blake3] <remote_path> [<local_path>]\n");
+    help_msg
+        .push_str("                                 Display a checksum of the file computed on\n");
+    help_msg.push_str("                                 server (default: sha256) and compare it\n");
+    help_msg.push_str("                                 with a local file. Ctrl-C cancels it.\n");
+    help_msg
+        .push_str("  stat <path>                    Display metadata of a file/directory (size,\n");
+    help_msg.push_str("                                 type, permissions, owner, times).\n");
+    help_msg.push_str("  cp [-r] [-f] <source_path> <destination_path>\n");
+    help_msg.push_str("                                 Copy a file (`-r`: a directory) on\n");
+    help_msg.push_str("                                 server. `-f` overwrites existing files.\n");
     help_msg
         .push_str("  mkdir <directory_name>         Create a new directory in current location.\n");
-    help_msg.push_str("  mv <file_dir_path> <new_name>  Rename a file/directory.\n");
-    help_msg.push_str("  rm <file_dir_path>             Remove a file/empty directory.\n");
+    help_msg.push_str("  mv [-f] <file_dir_path> <new_path>\n");
+    help_msg.push_str("                                 Move/rename a file/directory (into a\n");
+    help_msg.push_str("                                 directory if `new_path` is one). `-f`\n");
+    help_msg.push_str("                                 overwrites an existing destination.\n");
+    help_msg.push_str("  rm [-r] <file_dir_path>        Remove a file/empty directory (`-r`:\n");
+    help_msg.push_str("                                 with its contents, after confirmation;\n");
+    help_msg.push_str("                                 the server has to allow it).\n");
     help_msg.push_str("  clear                          Clear the screen.\n");
 
     help_msg.push_str(
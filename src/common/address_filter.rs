@@ -0,0 +1,77 @@
+use ipnet::{IpNet, Ipv4Net};
+use std::net::IpAddr;
+
+/// Ranges of addresses from which a server accepts connections.
+#[derive(Default, Debug)]
+pub struct AddressFilter {
+    /// If not empty, only addresses in these ranges are accepted.
+    pub allowed: Vec<IpNet>,
+    /// Addresses in these ranges are rejected (even if they are allowed).
+    pub denied: Vec<IpNet>,
+}
+
+impl AddressFilter {
+    pub fn is_allowed(&self, address: IpAddr) -> bool {
+        // IPv4 clients of a server listening on IPv6 have IPv4-mapped addresses:
+        let address = address.to_canonical();
+
+        !self.denied.iter().any(|network| network.contains(&address))
+            && (self.allowed.is_empty()
+                || self
+                    .allowed
+                    .iter()
+                    .any(|network| network.contains(&address)))
+    }
+}
+
+/// Parses a range in CIDR notation (e.g. `192.168.0.0/16` or `fd00::/8`). A single address is
+/// a range of one address.
+pub fn parse_network(text: &str) -> Option<IpNet> {
+    let network = text
+        .parse::<IpNet>()
+        .ok()
+        .or_else(|| text.parse::<IpAddr>().ok().map(IpNet::from))?
+        .trunc();
+
+    // Addresses are compared in the canonical form, so IPv4-mapped ranges become IPv4 ones:
+    match network {
+        IpNet::V6(network) if network.prefix_len() >= 96 => match network.addr().to_ipv4_mapped() {
+            Some(address) => Ipv4Net::new(address, network.prefix_len() - 96)
+                .ok()
+                .map(IpNet::V4),
+            None => Some(IpNet::V6(network)),
+        },
+        network => Some(network),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_address_filter() {
+        let filter = AddressFilter {
+            allowed: vec![
+                parse_network("192.168.0.0/16").unwrap(),
+                parse_network("fd00::/8").unwrap(),
+            ],
+            denied: vec![parse_network("192.168.1.7").unwrap()],
+        };
+
+        assert!(filter.is_allowed("192.168.5.1".parse().unwrap()));
+        assert!(filter.is_allowed("::ffff:192.168.5.1".parse().unwrap()));
+        assert!(filter.is_allowed("fd12::1".parse().unwrap()));
+        assert!(!filter.is_allowed("192.168.1.7".parse().unwrap()));
+        assert!(!filter.is_allowed("10.0.0.1".parse().unwrap()));
+        assert!(!filter.is_allowed("::1".parse().unwrap()));
+
+        assert!(AddressFilter::default().is_allowed("10.0.0.1".parse().unwrap()));
+        assert_eq!(parse_network("10.1.2.3/8"), parse_network("10.0.0.0/8"));
+        assert_eq!(parse_network("10.0.0.0/33"), None);
+        assert_eq!(
+            parse_network("::ffff:10.0.0.0/104"),
+            parse_network("10.0.0.0/8")
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::{
+    io::{self, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    task::{self, JoinHandle},
+};
+
+use crate::common::messages::MAX_FILE_FRAGMENT_SIZE;
+
+/// How many fragments may wait in a channel between the archiving thread and the connection.
+const ARCHIVE_CHANNEL_CAPACITY: usize = 16;
+
+/// Compression applied to a streamed tar archive.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TarCompression {
+    None,
+    Gzip,
+}
+
+impl TarCompression {
+    /// Decodes compression sent in a message.
+    pub fn from_u64(value: u64) -> Option<TarCompression> {
+        match value {
+            0 => Some(TarCompression::None),
+            1 => Some(TarCompression::Gzip),
+            _ => None,
+        }
+    }
+
+    /// Encodes compression so that it can be sent in a message.
+    pub fn to_u64(self) -> u64 {
+        match self {
+            TarCompression::None => 0,
+            TarCompression::Gzip => 1,
+        }
+    }
+
+    /// Returns extension of a file holding an archive with this compression.
+    pub fn file_extension(self) -> &'static str {
+        match self {
+            TarCompression::None => "tar",
+            TarCompression::Gzip => "tar.gz",
+        }
+    }
+}
+
+/// `Write` adapter passing written blocks to an asynchronous receiver.
+struct ChannelWriter {
+    sender: Sender<Vec<u8>>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        self.sender
+            .blocking_send(buffer.to_vec())
+            .map_err(|_| io::Error::from(ErrorKind::BrokenPipe))?;
+
+        Ok(buffer.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// `Read` adapter reading blocks sent by an asynchronous sender (closing the channel means EOF).
+struct ChannelReader {
+    receiver: Receiver<Vec<u8>>,
+    block: Vec<u8>,
+    position: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.block.len() {
+            let Some(block) = self.receiver.blocking_recv() else {
+                return Ok(0);
+            };
+            self.block = block;
+            self.position = 0;
+        }
+
+        let read_bytes = buffer.len().min(self.block.len() - self.position);
+        buffer[..read_bytes]
+            .copy_from_slice(&self.block[self.position..self.position + read_bytes]);
+        self.position += read_bytes;
+
+        Ok(read_bytes)
+    }
+}
+
+/// Writes a tar archive of `directory_path` (stored under `archive_name`) to `writer`.
+fn write_tar<W: Write>(writer: W, directory_path: &Path, archive_name: &str) -> io::Result<W> {
+    let mut builder = tar::Builder::new(writer);
+    // Symbolic links are archived as links, so that nothing from outside the directory gets in:
+    builder.follow_symlinks(false);
+    builder.append_dir_all(archive_name, directory_path)?;
+
+    builder.into_inner()
+}
+
+/// Starts generating a tar archive of `directory_path` in a separate thread.
+/// Returns a channel with consecutive fragments of the archive and a handle with the result.
+pub fn spawn_tar_builder(
+    directory_path: PathBuf,
+    archive_name: String,
+    compression: TarCompression,
+) -> (Receiver<Vec<u8>>, JoinHandle<io::Result<()>>) {
+    let (sender, receiver) = mpsc::channel(ARCHIVE_CHANNEL_CAPACITY);
+
+    let handle = task::spawn_blocking(move || {
+        let writer = BufWriter::with_capacity(MAX_FILE_FRAGMENT_SIZE, ChannelWriter { sender });
+
+        let mut writer = match compression {
+            TarCompression::None => write_tar(writer, &directory_path, &archive_name)?,
+            TarCompression::Gzip => {
+                let encoder = GzEncoder::new(writer, Compression::default());
+                write_tar(encoder, &directory_path, &archive_name)?.finish()?
+            }
+        };
+
+        writer.flush()
+    });
+
+    (receiver, handle)
+}
+
+/// Starts unpacking a tar archive into `destination` in a separate thread.
+/// Returns a channel to which consecutive fragments of the archive should be sent (dropping it
+/// ends the archive) and a handle with the result.
+pub fn spawn_tar_extractor(
+    destination: PathBuf,
+    compression: TarCompression,
+) -> (Sender<Vec<u8>>, JoinHandle<io::Result<()>>) {
+    let (sender, receiver) = mpsc::channel(ARCHIVE_CHANNEL_CAPACITY);
+
+    let handle = task::spawn_blocking(move || {
+        let reader = ChannelReader {
+            receiver,
+            block: vec![],
+            position: 0,
+        };
+
+        match compression {
+            TarCompression::None => tar::Archive::new(reader).unpack(&destination),
+            TarCompression::Gzip => tar::Archive::new(GzDecoder::new(reader)).unpack(&destination),
+        }
+    });
+
+    (sender, handle)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tar_round_trip() {
+        let source = std::env::temp_dir().join("quick_transfer_test_tar_source");
+        let destination = std::env::temp_dir().join("quick_transfer_test_tar_destination");
+        let _ = fs::remove_dir_all(&source);
+        let _ = fs::remove_dir_all(&destination);
+        fs::create_dir_all(source.join("nested")).unwrap();
+        fs::write(source.join("nested/file.txt"), b"QuickTransfer").unwrap();
+
+        let (mut fragments, builder) = spawn_tar_builder(
+            source.clone(),
+            String::from("archive"),
+            TarCompression::Gzip,
+        );
+        let (sender, extractor) = spawn_tar_extractor(destination.clone(), TarCompression::Gzip);
+
+        while let Some(fragment) = fragments.recv().await {
+            sender.send(fragment).await.unwrap();
+        }
+        drop(sender);
+
+        builder.await.unwrap().unwrap();
+        extractor.await.unwrap().unwrap();
+        assert_eq!(
+            fs::read(destination.join("archive/nested/file.txt")).unwrap(),
+            b"QuickTransfer"
+        );
+
+        fs::remove_dir_all(&source).unwrap();
+        fs::remove_dir_all(&destination).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{self, ErrorKind, Read},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tokio::task::{self, JoinHandle};
+
+/// Size of blocks in which files are read while hashing.
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Hash function used to compute a checksum of a file.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChecksumAlgorithm {
+    Sha256,
+    Blake3,
+}
+
+impl ChecksumAlgorithm {
+    /// Decodes algorithm sent in a message.
+    pub fn from_u64(value: u64) -> Option<ChecksumAlgorithm> {
+        match value {
+            0 => Some(ChecksumAlgorithm::Sha256),
+            1 => Some(ChecksumAlgorithm::Blake3),
+            _ => None,
+        }
+    }
+
+    /// Encodes algorithm so that it can be sent in a message.
+    pub fn to_u64(self) -> u64 {
+        match self {
+            ChecksumAlgorithm::Sha256 => 0,
+            ChecksumAlgorithm::Blake3 => 1,
+        }
+    }
+
+    /// Parses algorithm name given by user.
+    pub fn from_name(name: &str) -> Option<ChecksumAlgorithm> {
+        match name.to_ascii_lowercase().as_str() {
+            "sha256" => Some(ChecksumAlgorithm::Sha256),
+            "blake3" => Some(ChecksumAlgorithm::Blake3),
+            _ => None,
+        }
+    }
+
+    /// Returns algorithm name displayed to user.
+    pub fn name(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+/// Computes a digest of contents read from `reader` (block by block).
+/// Returns `ErrorKind::Interrupted` error if `cancelled` gets set.
+pub fn digest(
+    mut reader: impl Read,
+    algorithm: ChecksumAlgorithm,
+    cancelled: &AtomicBool,
+) -> io::Result<Vec<u8>> {
+    let mut sha256 = Sha256::new();
+    let mut blake3 = blake3::Hasher::new();
+    let mut buffer = vec![0_u8; HASH_BUFFER_SIZE];
+
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            return Err(io::Error::from(ErrorKind::Interrupted));
+        }
+
+        let read_bytes = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read_bytes) => read_bytes,
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+
+        match algorithm {
+            ChecksumAlgorithm::Sha256 => sha256.update(&buffer[..read_bytes]),
+            ChecksumAlgorithm::Blake3 => {
+                blake3.update(&buffer[..read_bytes]);
+            }
+        }
+    }
+
+    Ok(match algorithm {
+        ChecksumAlgorithm::Sha256 => sha256.finalize().to_vec(),
+        ChecksumAlgorithm::Blake3 => blake3.finalize().as_bytes().to_vec(),
+    })
+}
+
+/// Starts computing a digest of a file in a separate thread.
+/// Returns a flag cancelling the computation and a handle with the digest.
+pub fn spawn_file_digest(
+    file_path: PathBuf,
+    algorithm: ChecksumAlgorithm,
+) -> (Arc<AtomicBool>, JoinHandle<io::Result<Vec<u8>>>) {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let cancelled_flag = cancelled.clone();
+
+    let handle =
+        task::spawn_blocking(move || digest(File::open(file_path)?, algorithm, &cancelled_flag));
+
+    (cancelled, handle)
+}
+
+/// Formats a digest as a lowercase hexadecimal string.
+pub fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_digest() {
+        let not_cancelled = AtomicBool::new(false);
+
+        let sha256 = digest(&b"abc"[..], ChecksumAlgorithm::Sha256, &not_cancelled).unwrap();
+        assert_eq!(
+            to_hex(&sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let blake3 = digest(&b"abc"[..], ChecksumAlgorithm::Blake3, &not_cancelled).unwrap();
+        assert_eq!(
+            to_hex(&blake3),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+
+        let cancelled = AtomicBool::new(true);
+        let error = digest(&b"abc"[..], ChecksumAlgorithm::Sha256, &cancelled).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Interrupted);
+    }
+}
@@ -0,0 +1,216 @@
+use std::{
+    fs::{self, File},
+    io::{self, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    task::{self, JoinHandle},
+};
+
+use crate::common::{
+    disk::{disk_space, total_usage},
+    messages::CopyProgress,
+};
+
+/// Size of blocks in which files are copied.
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// How often progress of a copy is reported.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// State of a running copy.
+struct Copy {
+    overwrite: bool,
+    progress: CopyProgress,
+    last_report: Instant,
+    sender: Sender<CopyProgress>,
+}
+
+impl Copy {
+    /// Copies a file/directory (recursively) from `source` to `destination`.
+    /// Symbolic links are copied as links.
+    fn copy(&mut self, source: &Path, destination: &Path) -> io::Result<()> {
+        let metadata = fs::symlink_metadata(source)?;
+
+        // Existing symbolic links are never followed (they could lead outside the root):
+        if self.overwrite
+            && fs::symlink_metadata(destination).is_ok_and(|metadata| metadata.is_symlink())
+        {
+            fs::remove_file(destination)?;
+        }
+
+        if metadata.is_dir() {
+            match fs::create_dir(destination) {
+                Err(error) if error.kind() == ErrorKind::AlreadyExists && self.overwrite => {}
+                result => result?,
+            }
+
+            let mut entries = fs::read_dir(source)?.collect::<io::Result<Vec<_>>>()?;
+            entries.sort_by_key(|entry| entry.file_name());
+            for entry in entries {
+                self.copy(&entry.path(), &destination.join(entry.file_name()))?;
+            }
+
+            fs::set_permissions(destination, metadata.permissions())
+        } else if metadata.is_symlink() {
+            self.copy_symlink(source, destination)
+        } else {
+            self.copy_file(source, destination)?;
+
+            fs::set_permissions(destination, metadata.permissions())
+        }
+    }
+
+    /// Copies contents of a regular file, reporting progress.
+    fn copy_file(&mut self, source: &Path, destination: &Path) -> io::Result<()> {
+        let mut source = File::open(source)?;
+        let mut destination = if self.overwrite {
+            File::create(destination)?
+        } else {
+            File::create_new(destination)?
+        };
+
+        let mut buffer = vec![0_u8; COPY_BUFFER_SIZE];
+        loop {
+            let read_bytes = match source.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read_bytes) => read_bytes,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            destination.write_all(&buffer[..read_bytes])?;
+
+            // We assume that usize <= u64:
+            self.progress.copied_bytes += u64::try_from(read_bytes).unwrap();
+            self.report_progress()?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates a symbolic link with the same target as `source`.
+    #[cfg(unix)]
+    fn copy_symlink(&mut self, source: &Path, destination: &Path) -> io::Result<()> {
+        let target = fs::read_link(source)?;
+        if self.overwrite && destination.is_file() {
+            fs::remove_file(destination)?;
+        }
+
+        std::os::unix::fs::symlink(target, destination)
+    }
+
+    #[cfg(not(unix))]
+    fn copy_symlink(&mut self, _source: &Path, _destination: &Path) -> io::Result<()> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+
+    /// Sends progress of the copy if it has not been sent recently.
+    fn report_progress(&mut self) -> io::Result<()> {
+        if self.last_report.elapsed() < PROGRESS_INTERVAL {
+            return Ok(());
+        }
+        self.last_report = Instant::now();
+
+        // The receiver is dropped only if the connection has failed:
+        self.sender
+            .blocking_send(self.progress.clone())
+            .map_err(|_| io::Error::from(ErrorKind::BrokenPipe))
+    }
+}
+
+/// Starts copying `source` to `destination` in a separate thread. If `overwrite` is set, existing
+/// files are overwritten and existing directories are merged. The copy fails with
+/// `ErrorKind::StorageFull` if there is not enough space for it; a destination created by a failed
+/// copy is removed. Returns a channel with progress reported every second and a handle with
+/// the result.
+pub fn spawn_copy(
+    source: PathBuf,
+    destination: PathBuf,
+    overwrite: bool,
+) -> (Receiver<CopyProgress>, JoinHandle<io::Result<()>>) {
+    let (sender, receiver) = mpsc::channel(1);
+
+    let handle = task::spawn_blocking(move || {
+        let total_bytes = total_usage(&source)?.size;
+
+        // An overwritten file frees its space:
+        let existing_destination = fs::symlink_metadata(&destination).ok();
+        let overwritten_size = existing_destination
+            .as_ref()
+            .filter(|metadata| metadata.is_file())
+            .map_or(0, |metadata| metadata.len());
+        let destination_directory = destination.parent().unwrap_or(&destination);
+        if disk_space(destination_directory)
+            .is_ok_and(|space| space.available.saturating_add(overwritten_size) < total_bytes)
+        {
+            return Err(io::Error::from(ErrorKind::StorageFull));
+        }
+
+        let mut copy = Copy {
+            overwrite,
+            progress: CopyProgress {
+                copied_bytes: 0,
+                total_bytes,
+            },
+            last_report: Instant::now(),
+            sender,
+        };
+
+        let result = copy.copy(&source, &destination);
+        if result.is_err() && existing_destination.is_none() {
+            // Symbolic links inside are removed themselves, their targets are left untouched:
+            let _ = match fs::symlink_metadata(&destination) {
+                Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&destination),
+                _ => fs::remove_file(&destination),
+            };
+        }
+
+        result
+    });
+
+    (receiver, handle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_copy() {
+        let directory = std::env::temp_dir().join("quick_transfer_test_copy");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("source/nested")).unwrap();
+        fs::write(directory.join("source/nested/file.txt"), b"QuickTransfer").unwrap();
+
+        let (_, handle) = spawn_copy(directory.join("source"), directory.join("copy"), false);
+        handle.await.unwrap().unwrap();
+        assert_eq!(
+            fs::read(directory.join("copy/nested/file.txt")).unwrap(),
+            b"QuickTransfer"
+        );
+
+        // Without overwriting, existing files are left untouched:
+        let (_, handle) = spawn_copy(
+            directory.join("source/nested/file.txt"),
+            directory.join("copy/nested/file.txt"),
+            false,
+        );
+        let error = handle.await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+
+        // A socket cannot be opened, so the copy fails after copying `file.txt`:
+        #[cfg(unix)]
+        {
+            let _socket =
+                std::os::unix::net::UnixListener::bind(directory.join("source/nested/socket"))
+                    .unwrap();
+            let (_, handle) = spawn_copy(directory.join("source"), directory.join("failed"), false);
+            assert!(handle.await.unwrap().is_err());
+            assert!(!directory.join("failed").exists());
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
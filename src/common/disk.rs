@@ -0,0 +1,115 @@
+use std::{fs, io, path::Path};
+
+use crate::common::messages::{DiskSpace, DiskUsage};
+
+/// Returns total, free and available space of the filesystem holding `path`.
+#[cfg(unix)]
+pub fn disk_space(path: &Path) -> io::Result<DiskSpace> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // SAFETY: `statvfs` is plain old data, for which all-zero bytes are a valid value.
+    let mut statistics: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    // SAFETY: `path` is a valid NUL-terminated string and `statistics` is a valid pointer.
+    if unsafe { libc::statvfs(path.as_ptr(), &mut statistics) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    #[allow(clippy::useless_conversion)] // The field types differ between platforms.
+    let fragment_size = u64::from(statistics.f_frsize);
+    #[allow(clippy::useless_conversion)]
+    Ok(DiskSpace {
+        total: u64::from(statistics.f_blocks) * fragment_size,
+        free: u64::from(statistics.f_bfree) * fragment_size,
+        available: u64::from(statistics.f_bavail) * fragment_size,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn disk_space(_path: &Path) -> io::Result<DiskSpace> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Computes the total size of files inside `path` (recursively, symbolic links are not followed
+/// and counted as files).
+pub fn total_usage(path: &Path) -> io::Result<DiskUsage> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(DiskUsage {
+            path: String::new(),
+            size: metadata.len(),
+            files: 1,
+        });
+    }
+
+    let mut usage = DiskUsage {
+        path: String::new(),
+        size: 0,
+        files: 0,
+    };
+
+    for entry in fs::read_dir(path)? {
+        let entry_usage = total_usage(&entry?.path())?;
+        usage.size += entry_usage.size;
+        usage.files += entry_usage.files;
+    }
+
+    Ok(usage)
+}
+
+/// Computes disk usage of `path` (displayed to the client as `displayed_path`). Unless
+/// `summarize` is set, usage of every position inside a directory precedes the total.
+pub fn disk_usage(
+    path: &Path,
+    displayed_path: &str,
+    summarize: bool,
+) -> io::Result<Vec<DiskUsage>> {
+    let mut usages = vec![];
+
+    if !summarize && path.is_dir() {
+        let mut entries = fs::read_dir(path)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let mut usage = total_usage(&entry.path())?;
+            usage.path = format!("{}/{}", displayed_path, entry.file_name().to_string_lossy());
+            usages.push(usage);
+        }
+    }
+
+    let mut total = total_usage(path)?;
+    total.path = String::from(displayed_path);
+    usages.push(total);
+
+    Ok(usages)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_disk_usage() {
+        let directory = std::env::temp_dir().join("quick_transfer_test_disk_usage");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("nested")).unwrap();
+        fs::write(directory.join("nested/a.txt"), [0_u8; 100]).unwrap();
+        fs::write(directory.join("nested/b.txt"), [0_u8; 50]).unwrap();
+        fs::write(directory.join("c.txt"), [0_u8; 10]).unwrap();
+
+        let usages = disk_usage(&directory, ".", false).unwrap();
+        let usages: Vec<(&str, u64)> = usages
+            .iter()
+            .map(|usage| (usage.path.as_str(), usage.files))
+            .collect();
+        assert_eq!(usages, vec![("./c.txt", 1), ("./nested", 2), (".", 3)]);
+
+        let summary = disk_usage(&directory, ".", true).unwrap();
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].size, 160);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
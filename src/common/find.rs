@@ -0,0 +1,252 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::UNIX_EPOCH,
+};
+use tokio::{
+    sync::mpsc::{self, Sender},
+    task::{self, JoinHandle},
+};
+
+use crate::common::messages::{FileType, FindEnd, FindMatch, FindQuery};
+
+/// How many matches may wait in a channel between the searching thread and the connection.
+const FIND_CHANNEL_CAPACITY: usize = 64;
+
+/// Maximal number of matches sent for one query.
+pub const MAX_FIND_RESULTS: u64 = 1000;
+
+/// Maximal depth of a search (the searched directory itself has depth 0).
+pub const MAX_FIND_DEPTH: u32 = 64;
+
+/// Checks whether `name` matches a glob `pattern`. Supported wildcards: `*` (any sequence of
+/// characters), `?` (one character) and `[...]` (one character from a set, e.g. `[a-z]`; `[!...]`
+/// negates the set).
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut pattern_index, mut name_index) = (0, 0);
+    // Position after the last `*` and the name position it has been matched against:
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while name_index < name.len() {
+        let matched_length = match pattern.get(pattern_index) {
+            Some('*') => {
+                backtrack = Some((pattern_index + 1, name_index));
+                pattern_index += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match_character_set(&pattern[pattern_index..], name[name_index]),
+            Some(character) if *character == name[name_index] => Some(1),
+            _ => None,
+        };
+
+        match (matched_length, backtrack) {
+            (Some(pattern_length), _) => {
+                pattern_index += pattern_length;
+                name_index += 1;
+            }
+            (None, Some((star_pattern_index, star_name_index))) => {
+                // Let the last `*` consume one more character:
+                backtrack = Some((star_pattern_index, star_name_index + 1));
+                pattern_index = star_pattern_index;
+                name_index = star_name_index + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[pattern_index..]
+        .iter()
+        .all(|character| *character == '*')
+}
+
+/// Matches `character` against a set starting at the beginning of `pattern` (`[...]`).
+/// Returns the length of the set in the pattern if it matches. An unclosed `[` is matched literally.
+fn match_character_set(pattern: &[char], character: char) -> Option<usize> {
+    let Some(set_length) = pattern
+        .iter()
+        .skip(2)
+        .position(|c| *c == ']')
+        .map(|end| end + 3)
+    else {
+        return (character == '[').then_some(1);
+    };
+
+    let (negated, set) = match pattern[1] {
+        '!' | '^' => (true, &pattern[2..set_length - 1]),
+        _ => (false, &pattern[1..set_length - 1]),
+    };
+
+    let mut matched = false;
+    let mut index = 0;
+    while index < set.len() {
+        if index + 2 < set.len() && set[index + 1] == '-' {
+            matched |= (set[index]..=set[index + 2]).contains(&character);
+            index += 3;
+        } else {
+            matched |= set[index] == character;
+            index += 1;
+        }
+    }
+
+    (matched != negated).then_some(set_length)
+}
+
+impl FindQuery {
+    /// Checks whether a directory position matches the query.
+    fn matches(&self, found: &FindMatch, name: &str) -> bool {
+        glob_match(&self.name_pattern, name)
+            && self
+                .file_type
+                .is_none_or(|file_type| file_type == found.file_type)
+            && self.min_size.is_none_or(|min_size| found.size >= min_size)
+            && self.max_size.is_none_or(|max_size| found.size <= max_size)
+            && self
+                .modified_after
+                .is_none_or(|after| found.modified.is_some_and(|modified| modified >= after))
+            && self
+                .modified_before
+                .is_none_or(|before| found.modified.is_some_and(|modified| modified <= before))
+    }
+}
+
+/// State of a directory walk.
+struct Search {
+    query: FindQuery,
+    max_depth: u32,
+    limit: u64,
+    found: u64,
+    sender: Sender<FindMatch>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Search {
+    /// Searches `directory_path` (`relative_path` is its path displayed to the client).
+    /// Returns `None` if the search should be stopped.
+    fn walk(&mut self, directory_path: &Path, relative_path: &str, depth: u32) -> Option<()> {
+        // Subdirectories which cannot be read are skipped:
+        let Ok(entries) = fs::read_dir(directory_path) else {
+            return Some(());
+        };
+
+        let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            if self.cancelled.load(Ordering::Relaxed) {
+                return None;
+            }
+
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = format!("{}/{}", relative_path, name);
+            // Symbolic links are never followed, so that the search stays inside the root:
+            let Ok(metadata) = entry.path().symlink_metadata() else {
+                continue;
+            };
+
+            let file_type = if metadata.is_symlink() {
+                FileType::Symlink
+            } else if metadata.is_dir() {
+                FileType::Directory
+            } else if metadata.is_file() {
+                FileType::File
+            } else {
+                FileType::Other
+            };
+            let found = FindMatch {
+                path,
+                file_type,
+                size: metadata.len(),
+                modified: metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .and_then(|duration| duration.as_secs().try_into().ok()),
+            };
+
+            if self.query.matches(&found, &name) {
+                if self.found == self.limit {
+                    return None;
+                }
+                self.found += 1;
+
+                self.sender.blocking_send(found.clone()).ok()?;
+            }
+
+            if file_type == FileType::Directory && depth < self.max_depth {
+                self.walk(&entry.path(), &found.path, depth + 1)?;
+            }
+        }
+
+        Some(())
+    }
+}
+
+/// Starts searching `directory_path` in a separate thread. Returns a flag cancelling the search
+/// when set, a channel with consecutive matches and a handle with the way the search has ended.
+pub fn spawn_find(
+    directory_path: PathBuf,
+    query: FindQuery,
+) -> (
+    Arc<AtomicBool>,
+    mpsc::Receiver<FindMatch>,
+    JoinHandle<FindEnd>,
+) {
+    let (sender, receiver) = mpsc::channel(FIND_CHANNEL_CAPACITY);
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let cancelled_flag = cancelled.clone();
+
+    let handle = task::spawn_blocking(move || {
+        if fs::read_dir(&directory_path).is_err() {
+            return FindEnd::ErrorReading;
+        }
+
+        let mut search = Search {
+            max_depth: query
+                .max_depth
+                .unwrap_or(MAX_FIND_DEPTH)
+                .min(MAX_FIND_DEPTH),
+            limit: query
+                .limit
+                .unwrap_or(MAX_FIND_RESULTS)
+                .min(MAX_FIND_RESULTS),
+            query,
+            found: 0,
+            sender,
+            cancelled: cancelled_flag,
+        };
+
+        match search.walk(&directory_path, ".", 1) {
+            Some(()) => FindEnd::Completed,
+            None if search.cancelled.load(Ordering::Relaxed) => FindEnd::Cancelled,
+            None => FindEnd::LimitReached,
+        }
+    });
+
+    (cancelled, receiver, handle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.txt", "notes.txt"));
+        assert!(!glob_match("*.txt", "notes.txt.bak"));
+        assert!(glob_match("a?c", "abc"));
+        assert!(glob_match("*a*b*", "xxaxxbxx"));
+        assert!(glob_match("[a-c]at", "bat"));
+        assert!(!glob_match("[!a-c]at", "bat"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("[x", "[x"));
+        assert!(!glob_match("abc", "ab"));
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    fs::{self, File, Metadata},
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+    time::Duration,
+};
+
+use crate::common::messages::{FollowUpdate, MAX_READ_SIZE};
+
+/// How often a followed file is checked for changes.
+pub const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Maximal number of bytes read from a followed file in one poll (the rest is read in next polls,
+/// so that a quickly growing file does not stop the connection from being served).
+const MAX_POLL_SIZE: usize = 16 * MAX_READ_SIZE;
+
+/// A file on the server followed by a client (`tail -f`).
+pub struct FollowedFile {
+    file: File,
+    position: u64,
+}
+
+impl FollowedFile {
+    /// Opens `path` to be followed from byte `offset` (or from its end, if it is shorter).
+    pub fn open(path: &Path, offset: u64) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let position = offset.min(file.metadata()?.len());
+
+        Ok(FollowedFile { file, position })
+    }
+
+    /// Returns changes of the file since the last poll. `current_path` is the followed path
+    /// resolved again (`None` if it cannot be accessed at the moment): if it leads to another file,
+    /// the file has been rotated and the new one is followed from its beginning.
+    pub fn poll(&mut self, current_path: Option<&Path>) -> io::Result<Vec<FollowUpdate>> {
+        let mut updates = vec![];
+
+        if self.file.metadata()?.len() < self.position {
+            self.position = 0;
+            updates.push(FollowUpdate::Truncated);
+        }
+        self.read_appended(&mut updates)?;
+
+        let current_metadata = current_path.and_then(|path| fs::metadata(path).ok());
+        if let (Some(path), Some(current_metadata)) = (current_path, current_metadata) {
+            if !is_same_file(&self.file.metadata()?, &current_metadata) {
+                // Whatever has been appended to the old file has already been read:
+                self.file = File::open(path)?;
+                self.position = 0;
+                updates.push(FollowUpdate::Rotated);
+                self.read_appended(&mut updates)?;
+            }
+        }
+
+        Ok(updates)
+    }
+
+    /// Reads bytes appended after the current position (at most `MAX_POLL_SIZE`).
+    fn read_appended(&mut self, updates: &mut Vec<FollowUpdate>) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(self.position))?;
+
+        let mut content = vec![];
+        // We assume that usize <= u64:
+        (&mut self.file)
+            .take(MAX_POLL_SIZE.try_into().unwrap())
+            .read_to_end(&mut content)?;
+        self.position += u64::try_from(content.len()).unwrap();
+
+        updates.extend(
+            content
+                .chunks(MAX_READ_SIZE)
+                .map(|chunk| FollowUpdate::Appended(chunk.to_vec())),
+        );
+
+        Ok(())
+    }
+}
+
+/// Checks whether two metadata describe the same file.
+#[cfg(unix)]
+fn is_same_file(a: &Metadata, b: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_a: &Metadata, _b: &Metadata) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_followed_file() {
+        let directory = std::env::temp_dir().join("quick_transfer_test_followed_file");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("log.txt");
+        fs::write(&path, b"old\n").unwrap();
+
+        let mut followed = FollowedFile::open(&path, 4).unwrap();
+        assert!(followed.poll(Some(&path)).unwrap().is_empty());
+
+        File::options()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"new\n")
+            .unwrap();
+        assert_eq!(
+            followed.poll(Some(&path)).unwrap(),
+            vec![FollowUpdate::Appended(b"new\n".to_vec())]
+        );
+
+        File::create(&path).unwrap().write_all(b"x").unwrap();
+        assert_eq!(
+            followed.poll(Some(&path)).unwrap(),
+            vec![
+                FollowUpdate::Truncated,
+                FollowUpdate::Appended(b"x".to_vec())
+            ]
+        );
+
+        // Rotation: the followed file is renamed and a new one is created in its place.
+        fs::rename(&path, directory.join("log.txt.1")).unwrap();
+        fs::write(&path, b"rotated\n").unwrap();
+        assert_eq!(
+            followed.poll(Some(&path)).unwrap(),
+            vec![
+                FollowUpdate::Rotated,
+                FollowUpdate::Appended(b"rotated\n".to_vec())
+            ]
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
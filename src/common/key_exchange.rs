@@ -0,0 +1,236 @@
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use sha2::Sha256;
+use std::time::Duration;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+use crate::common::{ProgramRole, QuickTransferError};
+
+/// Length of an X25519 public key sent during the handshake.
+pub const PUBLIC_KEY_LENGTH: usize = 32;
+
+/// Length of a random challenge sent by each side during the handshake.
+pub const CHALLENGE_LENGTH: usize = 32;
+
+/// Length of a proof (HMAC-SHA256) that a side knows the pre-shared key.
+pub const PROOF_LENGTH: usize = 32;
+
+/// Default number of bytes encrypted with one key before it is replaced.
+pub const DEFAULT_REKEY_BYTES: u64 = 1 << 30;
+
+/// Default time after which a key is replaced (checked when a message is sent).
+pub const DEFAULT_REKEY_SECONDS: u64 = 60 * 60;
+
+/// Number of messages encrypted with one key before it is replaced (random 96-bit nonces
+/// should not be used much more than 2^32 times with one key).
+const REKEY_MESSAGES: u64 = 1 << 24;
+
+/// Context string of keys derived when a key is replaced.
+const REKEY_INFO: &[u8] = b"QuickTransfer rekey";
+
+/// Context strings binding derived keys to QuickTransfer sessions and to a direction
+/// (so that frames cannot be reflected back to their sender).
+const CLIENT_TO_SERVER_KEY_INFO: &[u8] = b"QuickTransfer client to server key";
+const SERVER_TO_CLIENT_KEY_INFO: &[u8] = b"QuickTransfer server to client key";
+
+/// Keys of both directions of a session, as seen by one side.
+pub struct SessionKeys {
+    pub sending: [u8; 32],
+    pub receiving: [u8; 32],
+}
+
+/// Limits after reaching which the sending key of a connection is replaced.
+#[derive(Copy, Clone, Debug)]
+pub struct RekeyLimits {
+    pub bytes: u64,
+    pub messages: u64,
+    pub interval: Duration,
+}
+
+impl RekeyLimits {
+    pub fn new(bytes: u64, seconds: u64) -> RekeyLimits {
+        RekeyLimits {
+            bytes,
+            messages: REKEY_MESSAGES,
+            interval: Duration::from_secs(seconds),
+        }
+    }
+}
+
+impl Default for RekeyLimits {
+    fn default() -> Self {
+        RekeyLimits::new(DEFAULT_REKEY_BYTES, DEFAULT_REKEY_SECONDS)
+    }
+}
+
+/// Derives a key replacing `key` (both sides derive the same one, so no exchange is needed).
+pub fn next_key(key: &[u8; 32]) -> [u8; 32] {
+    let mut next_key = [0_u8; 32];
+    Hkdf::<Sha256>::new(None, key)
+        .expand(REKEY_INFO, &mut next_key)
+        .unwrap();
+
+    next_key
+}
+
+/// Generates a random challenge for a new handshake.
+pub fn random_challenge() -> [u8; CHALLENGE_LENGTH] {
+    let mut challenge = [0_u8; CHALLENGE_LENGTH];
+    OsRng.fill_bytes(&mut challenge);
+
+    challenge
+}
+
+/// Everything exchanged in a handshake that both sides prove the knowledge of the pre-shared
+/// key over (so that proofs cannot be reused in other handshakes).
+pub struct HandshakeTranscript {
+    pub client_public_key: [u8; PUBLIC_KEY_LENGTH],
+    pub client_challenge: [u8; CHALLENGE_LENGTH],
+    pub server_public_key: [u8; PUBLIC_KEY_LENGTH],
+    pub server_challenge: [u8; CHALLENGE_LENGTH],
+}
+
+impl HandshakeTranscript {
+    fn mac(&self, pre_shared_key: &[u8; 32], role: ProgramRole) -> Hmac<Sha256> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(pre_shared_key).unwrap();
+        mac.update(match role {
+            ProgramRole::Client => b"QuickTransfer client proof",
+            ProgramRole::Server => b"QuickTransfer server proof",
+        });
+        mac.update(&self.client_public_key);
+        mac.update(&self.client_challenge);
+        mac.update(&self.server_public_key);
+        mac.update(&self.server_challenge);
+
+        mac
+    }
+
+    /// Computes a proof sent by the side with `role`.
+    pub fn proof(&self, pre_shared_key: &[u8; 32], role: ProgramRole) -> [u8; PROOF_LENGTH] {
+        self.mac(pre_shared_key, role)
+            .finalize()
+            .into_bytes()
+            .into()
+    }
+
+    /// Checks (in constant time) a proof received from the side with `role`.
+    pub fn verify(&self, pre_shared_key: &[u8; 32], role: ProgramRole, proof: &[u8]) -> bool {
+        self.mac(pre_shared_key, role).verify_slice(proof).is_ok()
+    }
+}
+
+/// One side of an ephemeral X25519 key exchange. The pre-shared key (from `-k` or derived from
+/// a passphrase) only authenticates the exchange: the session key depends on the ephemeral
+/// secrets, so recorded sessions cannot be decrypted even if the pre-shared key leaks.
+pub struct KeyExchange {
+    secret: EphemeralSecret,
+    public_key: PublicKey,
+}
+
+impl KeyExchange {
+    pub fn new() -> KeyExchange {
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let public_key = PublicKey::from(&secret);
+
+        KeyExchange { secret, public_key }
+    }
+
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_LENGTH] {
+        self.public_key.to_bytes()
+    }
+
+    /// Derives the session keys with HKDF-SHA256 (the pre-shared key is used as the salt,
+    /// so peers not knowing it end up with different keys).
+    pub fn session_keys(
+        self,
+        pre_shared_key: &[u8; 32],
+        remote_public_key: &[u8; PUBLIC_KEY_LENGTH],
+        role: ProgramRole,
+    ) -> Result<SessionKeys, QuickTransferError> {
+        let own_public_key = self.public_key();
+        let shared_secret = self
+            .secret
+            .diffie_hellman(&PublicKey::from(*remote_public_key));
+        // A low-order public key would make the shared secret predictable:
+        if !shared_secret.was_contributory() {
+            return Err(QuickTransferError::SentInvalidData(role));
+        }
+
+        let (client_public_key, server_public_key) = match role {
+            ProgramRole::Client => (&own_public_key, remote_public_key),
+            ProgramRole::Server => (remote_public_key, &own_public_key),
+        };
+        let hkdf = Hkdf::<Sha256>::new(Some(pre_shared_key), shared_secret.as_bytes());
+        let expand = |label: &[u8]| {
+            let mut key = [0_u8; 32];
+            hkdf.expand(
+                &[label, client_public_key, server_public_key].concat(),
+                &mut key,
+            )
+            .map(|_| key)
+            .map_err(|_| QuickTransferError::Fatal)
+        };
+        let client_to_server = expand(CLIENT_TO_SERVER_KEY_INFO)?;
+        let server_to_client = expand(SERVER_TO_CLIENT_KEY_INFO)?;
+
+        Ok(match role {
+            ProgramRole::Client => SessionKeys {
+                sending: client_to_server,
+                receiving: server_to_client,
+            },
+            ProgramRole::Server => SessionKeys {
+                sending: server_to_client,
+                receiving: client_to_server,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_session_keys() {
+        let client = KeyExchange::new();
+        let server = KeyExchange::new();
+        let (client_public_key, server_public_key) = (client.public_key(), server.public_key());
+
+        let client_keys = client
+            .session_keys(&[1; 32], &server_public_key, ProgramRole::Client)
+            .unwrap();
+        let server_keys = server
+            .session_keys(&[1; 32], &client_public_key, ProgramRole::Server)
+            .unwrap();
+        assert_eq!(client_keys.sending, server_keys.receiving);
+        assert_eq!(client_keys.receiving, server_keys.sending);
+        assert_ne!(client_keys.sending, client_keys.receiving);
+
+        let other_server = KeyExchange::new();
+        let other_keys = other_server
+            .session_keys(&[2; 32], &client_public_key, ProgramRole::Server)
+            .unwrap();
+        assert_ne!(client_keys.sending, other_keys.receiving);
+
+        let low_order_keys =
+            KeyExchange::new().session_keys(&[1; 32], &[0; 32], ProgramRole::Client);
+        assert!(low_order_keys.is_err());
+    }
+
+    #[test]
+    fn test_handshake_proof() {
+        let transcript = HandshakeTranscript {
+            client_public_key: [1; PUBLIC_KEY_LENGTH],
+            client_challenge: random_challenge(),
+            server_public_key: [2; PUBLIC_KEY_LENGTH],
+            server_challenge: random_challenge(),
+        };
+
+        let proof = transcript.proof(&[3; 32], ProgramRole::Client);
+        assert!(transcript.verify(&[3; 32], ProgramRole::Client, &proof));
+        // A proof of the other side or with another key is rejected:
+        assert!(!transcript.verify(&[3; 32], ProgramRole::Server, &proof));
+        assert!(!transcript.verify(&[4; 32], ProgramRole::Client, &proof));
+    }
+}
@@ -4,7 +4,18 @@ use serde::{Deserialize, Serialize};
 pub const MESSAGE_INIT: &str = "INIT____";
 pub const MESSAGE_INIT_ENC: &str = "INIT_ENC";
 pub const MESSAGE_NOT_ENC: &str = "NOT__ENC";
+pub const MESSAGE_INIT_PASSPHRASE: &str = "INIT_PWD";
+pub const MESSAGE_NOT_PASSPHRASE: &str = "NOT__PWD";
+pub const MESSAGE_ENCRYPTION_REQUIRED: &str = "ENC_REQD";
+pub const MESSAGE_CHALLENGE: &str = "CHALLNGE";
+pub const MESSAGE_AUTH: &str = "AUTH____";
+pub const MESSAGE_AUTH_FAIL: &str = "AUTHFAIL";
 pub const MESSAGE_OK: &str = "OK______";
+pub const MESSAGE_LOGIN_REQUIRED: &str = "LOGINREQ";
+pub const MESSAGE_LOGIN: &str = "LOGIN___";
+pub const MESSAGE_LOGIN_FAIL: &str = "LOGNFAIL";
+pub const MESSAGE_PERMISSIONS: &str = "PERMSSNS";
+pub const MESSAGE_PERMISSION_DENIED: &str = "PERMDENY";
 pub const MESSAGE_DIR: &str = "DIR_____";
 pub const MESSAGE_CD: &str = "CD______";
 pub const MESSAGE_CDANSWER: &str = "CDANSWER";
@@ -14,6 +25,9 @@ pub const MESSAGE_DOWNLOAD_FAIL: &str = "DOWNFAIL";
 pub const MESSAGE_DOWNLOAD_SUCCESS: &str = "DOWNSUCC";
 pub const MESSAGE_UPLOAD: &str = "UPLOAD__";
 pub const MESSAGE_UPLOAD_RESULT: &str = "UPLOADRE";
+pub const MESSAGE_UPLOAD_ACCEPT: &str = "UPLOADOK";
+pub const MESSAGE_UPLOAD_CHECK: &str = "UPLCHECK";
+pub const MESSAGE_UPLOAD_CHECK_ANSWER: &str = "UPLCHKAN";
 pub const MESSAGE_MKDIR: &str = "MKDIR___";
 pub const MESSAGE_MKDIRANS: &str = "MKDIRANS";
 pub const MESSAGE_RENAME: &str = "RENAME__";
@@ -21,11 +35,38 @@ pub const MESSAGE_RENAME_ANSWER: &str = "RENAMEAN";
 pub const MESSAGE_DISCONNECT: &str = "DISCONN_";
 pub const MESSAGE_REMOVE: &str = "REMOVE__";
 pub const MESSAGE_REMOVE_ANSWER: &str = "REMOVEAN";
+pub const MESSAGE_DOWNLOAD_TAR: &str = "DOWNTAR_";
+pub const MESSAGE_STREAM_DATA: &str = "STRMDATA";
+pub const MESSAGE_STREAM_END: &str = "STRMEND_";
+pub const MESSAGE_UPLOAD_STREAM: &str = "UPLDSTRM";
+pub const MESSAGE_READ: &str = "READ____";
+pub const MESSAGE_READ_ANSWER: &str = "READANS_";
+pub const MESSAGE_LS_LONG: &str = "LSLONG__";
+pub const MESSAGE_DIR_LONG: &str = "DIRLONG_";
+pub const MESSAGE_FIND: &str = "FIND____";
+pub const MESSAGE_FIND_MATCH: &str = "FINDMTCH";
+pub const MESSAGE_FIND_END: &str = "FINDEND_";
+pub const MESSAGE_DU: &str = "DU______";
+pub const MESSAGE_DU_ANSWER: &str = "DUANSWER";
+pub const MESSAGE_DF: &str = "DF______";
+pub const MESSAGE_DF_ANSWER: &str = "DFANSWER";
+pub const MESSAGE_CHECKSUM: &str = "CHECKSUM";
+pub const MESSAGE_CHECKSUM_ANSWER: &str = "CHECKANS";
+pub const MESSAGE_CANCEL: &str = "CANCEL__";
+pub const MESSAGE_COPY: &str = "COPY____";
+pub const MESSAGE_COPY_PROGRESS: &str = "COPYPROG";
+pub const MESSAGE_COPY_ANSWER: &str = "COPYANS_";
+pub const MESSAGE_STAT: &str = "STAT____";
+pub const MESSAGE_STAT_ANSWER: &str = "STATANS_";
+pub const MESSAGE_FOLLOW: &str = "FOLLOW__";
+pub const MESSAGE_FOLLOW_UPDATE: &str = "FOLLOWUP";
+pub const MESSAGE_FOLLOW_END: &str = "FOLLOWEN";
 
 // Constants:
 pub const HEADER_NAME_LENGTH: usize = 8;
 pub const MESSAGE_LENGTH_LENGTH: usize = 8;
 pub const MAX_FILE_FRAGMENT_SIZE: usize = 1024;
+pub const MAX_READ_SIZE: usize = 64 * 1024;
 
 // Messages bodies:
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -35,6 +76,9 @@ pub struct UnencryptedMessage {
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct EncryptedMessage {
+    pub number: u64,
+    /// Set for an (empty) message after which the key of the sending side is replaced.
+    pub rekey: bool,
     pub nonce: Vec<u8>,
     pub content: Vec<u8>,
 }
@@ -71,6 +115,15 @@ pub enum FileFail {
     IllegalFile,
     ErrorOpeningFile,
     ErrorCreatingFile,
+    ErrorWritingFile,
+    TransferInterrupted,
+    NotEnoughSpace,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum StreamEnd {
+    Fail,
+    Success,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -79,6 +132,19 @@ pub enum UploadResult {
     Success,
 }
 
+/// The state of an upload destination on the server. A digest is computed only if the file
+/// already exists with the same size as the uploaded one.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum UploadCheckAnswer {
+    Missing,
+    DifferentSize,
+    IllegalFile,
+    ErrorReading,
+    Digest(Vec<u8>),
+    /// The destination has the same size, but the client may not read its contents.
+    DigestNotPermitted,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub enum MkdirAnswer {
     DirectoryAlreadyExists,
@@ -93,8 +159,15 @@ pub enum RenameAnswer {
     IllegalFileDir,
     ErrorRenaming,
     Success,
+    DestinationDoesNotExist,
+    IllegalDestination,
+    DestinationExists,
+    DestinationInsideSource,
 }
 
+/// Flags of a "RENAME" message (bits of a u64).
+pub const RENAME_OVERWRITE: u64 = 1;
+
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub enum RemoveAnswer {
     FileDirDoesNotExist,
@@ -102,4 +175,197 @@ pub enum RemoveAnswer {
     ErrorRemoving,
     DirectoryNotEmpty,
     Success,
+    RecursiveRemoveNotAllowed,
+    ContainsCurrentDirectory,
+    Preview(DiskUsage),
+}
+
+/// Flags of a "REMOVE" message (bits of a u64).
+pub const REMOVE_RECURSIVE: u64 = 1;
+pub const REMOVE_PREVIEW: u64 = 2;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum ReadRange {
+    Bytes { offset: u64, length: u64 },
+    HeadLines(u64),
+    TailLines(u64),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct FileFragment {
+    pub offset: u64,
+    pub content: Vec<u8>,
+    pub file_size: u64,
+    pub truncated: bool,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum ReadAnswer {
+    FileDoesNotExist,
+    IllegalFile,
+    ErrorReading,
+    Success(FileFragment),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum FileType {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct FileOwner {
+    pub user_id: u32,
+    pub group_id: u32,
+    pub user_name: Option<String>,
+    pub group_name: Option<String>,
+}
+
+/// Times are given in seconds since the Unix epoch; `mode` holds Unix permission bits (if available).
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct FileMetadata {
+    pub name: String,
+    pub file_type: FileType,
+    pub size: u64,
+    pub mode: Option<u32>,
+    pub read_only: bool,
+    pub owner: Option<FileOwner>,
+    pub modified: Option<i64>,
+    pub accessed: Option<i64>,
+    pub created: Option<i64>,
+    pub symlink_target: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum StatAnswer {
+    FileDirDoesNotExist,
+    IllegalFileDir,
+    ErrorReadingMetadata,
+    Success(FileMetadata),
+}
+
+/// Directory contents with metadata of every position (sent in answer to a "LS_LONG").
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct DetailedDirectoryContents {
+    pub location: String,
+    pub positions: Vec<FileMetadata>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum MessageDetailedDirectoryContents {
+    ReadingDirectoryError,
+    Success(DetailedDirectoryContents),
+}
+
+/// Criteria of a search; every given criterion has to be met. Times are given in seconds since
+/// the Unix epoch.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct FindQuery {
+    pub name_pattern: String,
+    pub file_type: Option<FileType>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub modified_after: Option<i64>,
+    pub modified_before: Option<i64>,
+    pub max_depth: Option<u32>,
+    pub limit: Option<u64>,
+}
+
+/// A position found by a search (`path` is relative to the searched directory).
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct FindMatch {
+    pub path: String,
+    pub file_type: FileType,
+    pub size: u64,
+    pub modified: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum FindEnd {
+    Completed,
+    LimitReached,
+    ErrorReading,
+    Cancelled,
+}
+
+/// Total size (in bytes) and number of files inside a file/directory.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct DiskUsage {
+    pub path: String,
+    pub size: u64,
+    pub files: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum DuAnswer {
+    FileDirDoesNotExist,
+    IllegalFileDir,
+    ErrorReading,
+    Success(Vec<DiskUsage>),
+}
+
+/// Space (in bytes) of a filesystem. `available` is the free space usable by the server.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct DiskSpace {
+    pub total: u64,
+    pub free: u64,
+    pub available: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum DfAnswer {
+    ErrorReading,
+    Success(DiskSpace),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum ChecksumAnswer {
+    FileDoesNotExist,
+    IllegalFile,
+    ErrorReading,
+    Cancelled,
+    Success(Vec<u8>),
+}
+
+/// Flags of a "COPY" message (bits of a u64).
+pub const COPY_RECURSIVE: u64 = 1;
+pub const COPY_OVERWRITE: u64 = 2;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct CopyProgress {
+    pub copied_bytes: u64,
+    pub total_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum CopyAnswer {
+    FileDirDoesNotExist,
+    IllegalFileDir,
+    IllegalDestination,
+    DestinationExists,
+    DestinationInsideSource,
+    IsDirectory,
+    ErrorCopying,
+    Success,
+    NotEnoughSpace,
+}
+
+/// A change of a followed file.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum FollowUpdate {
+    Appended(Vec<u8>),
+    /// The file has been truncated, it is followed from its beginning.
+    Truncated,
+    /// The file has been replaced by another one (e.g. rotated), which is followed from its beginning.
+    Rotated,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum FollowEnd {
+    FileDoesNotExist,
+    IllegalFile,
+    ErrorReading,
+    Stopped,
 }
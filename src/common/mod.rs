@@ -1,19 +1,50 @@
+use address_filter::AddressFilter;
 use aes::{cipher::typenum, Aes256};
-use aes_gcm::AesGcm;
+use aes_gcm::{aead::KeyInit, AesGcm};
+use checksum::ChecksumAlgorithm;
 use core::fmt;
-use messages::{DirectoryContents, DirectoryPosition, MessageDirectoryContents};
+use key_exchange::{next_key, RekeyLimits, SessionKeys};
+use messages::{
+    ChecksumAnswer, CopyAnswer, CopyProgress, DetailedDirectoryContents, DfAnswer,
+    DirectoryContents, DirectoryPosition, DuAnswer, FileMetadata, FileOwner, FileType, FindEnd,
+    FindMatch, FindQuery, FollowEnd, FollowUpdate, MessageDetailedDirectoryContents,
+    MessageDirectoryContents, ReadAnswer, ReadRange, StatAnswer, UploadCheckAnswer, UploadResult,
+    MESSAGE_CHECKSUM_ANSWER, MESSAGE_DF_ANSWER, MESSAGE_DU_ANSWER, MESSAGE_READ_ANSWER,
+    MESSAGE_STAT_ANSWER, MESSAGE_UPLOAD_ACCEPT, MESSAGE_UPLOAD_CHECK_ANSWER, MESSAGE_UPLOAD_RESULT,
+};
+use permissions::{Permission, Permissions};
+use rate_limit::RateLimits;
 use std::{
-    fs::{self, DirEntry},
+    fs::{self, DirEntry, File, Metadata},
+    future::Future,
     io::{self, ErrorKind},
-    path::Path,
+    path::{Path, PathBuf},
     str,
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 use thiserror::Error;
-use tokio::net::TcpStream;
-
+use tls::Fingerprint;
+use tokio::io::{AsyncRead, AsyncWrite, BufReader};
+use users::UserDatabase;
+
+pub mod address_filter;
+pub mod archive;
+pub mod checksum;
+pub mod copy;
+pub mod disk;
+pub mod find;
+pub mod follow;
+pub mod key_exchange;
 pub mod messages;
+pub mod passphrase;
+pub mod permissions;
+pub mod rate_limit;
 mod receive_utils;
 mod send_utils;
+pub mod tls;
+pub mod users;
+
+pub use receive_utils::{CopyEvent, FindEvent, FollowEvent, StreamFragment};
 
 // Generic constants:
 pub const DEFAULT_PORT: u16 = 47842;
@@ -56,6 +87,68 @@ pub struct ProgramOptions {
     pub root_directory: String,
     pub timeout: u16,
     pub aes_key: Option<[u8; 32]>,
+    pub passphrase: Option<String>,
+    pub require_encryption: bool,
+    pub command: Vec<String>,
+    pub allow_recursive_delete: bool,
+    pub symlink_policy: SymlinkPolicy,
+    pub rekey_limits: RekeyLimits,
+    pub tls: bool,
+    pub tls_directory: PathBuf,
+    pub tls_fingerprint: Option<Fingerprint>,
+    pub users: Option<UserDatabase>,
+    pub user_name: Option<String>,
+    pub password: Option<String>,
+    pub permissions: Permissions,
+    pub address_filter: AddressFilter,
+    pub rate_limits: RateLimits,
+}
+
+/// The way a server treats symbolic links inside the shared directory.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SymlinkPolicy {
+    /// Symbolic links cannot be followed nor operated on.
+    Deny,
+    /// Symbolic links are followed if their targets are inside the root directory.
+    FollowWithinRoot,
+    /// Symbolic links are followed wherever they point to.
+    FollowAll,
+    /// Symbolic links are never followed, but they can be operated on (e.g. removed).
+    ShowAsLink,
+}
+
+impl SymlinkPolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "deny" => Some(SymlinkPolicy::Deny),
+            "follow-within-root" => Some(SymlinkPolicy::FollowWithinRoot),
+            "follow-all" => Some(SymlinkPolicy::FollowAll),
+            "show-as-link" => Some(SymlinkPolicy::ShowAsLink),
+            _ => None,
+        }
+    }
+
+    /// Checks whether the symbolic link `link_path` may be followed.
+    pub fn allows_following(&self, link_path: &Path, root_directory_path: &Path) -> bool {
+        match self {
+            SymlinkPolicy::Deny | SymlinkPolicy::ShowAsLink => false,
+            SymlinkPolicy::FollowWithinRoot => link_path
+                .canonicalize()
+                .is_ok_and(|target| target.starts_with(root_directory_path)),
+            SymlinkPolicy::FollowAll => true,
+        }
+    }
+
+    /// Checks whether the target of the symbolic link `link_path` may be shown to clients.
+    pub fn allows_showing_target(&self, link_path: &Path, root_directory_path: &Path) -> bool {
+        match self {
+            SymlinkPolicy::Deny => false,
+            SymlinkPolicy::FollowWithinRoot | SymlinkPolicy::ShowAsLink => link_path
+                .canonicalize()
+                .is_ok_and(|target| target.starts_with(root_directory_path)),
+            SymlinkPolicy::FollowAll => true,
+        }
+    }
 }
 
 /// A helper providing an abstraction for sending and receiving messages.
@@ -70,7 +163,7 @@ impl CommunicationAgent<'_> {
         stream: &mut QuickTransferStream,
         role: ProgramRole,
         timeout: u16,
-    ) -> CommunicationAgent {
+    ) -> CommunicationAgent<'_> {
         CommunicationAgent {
             stream,
             role,
@@ -81,49 +174,108 @@ impl CommunicationAgent<'_> {
 
 type CipherType = AesGcm<Aes256, typenum::U12, typenum::U16>;
 
+/// Associated data of an encrypted message: its number and whether it is a rekey message.
+fn associated_data(number: u64, rekey: bool) -> [u8; 9] {
+    let mut data = [0_u8; 9];
+    data[..8].copy_from_slice(&number.to_be_bytes());
+    data[8] = rekey.into();
+    data
+}
+
+/// State of one direction of an encrypted connection.
+struct EncryptedDirection {
+    key: [u8; 32],
+    cipher: CipherType,
+    /// Number of all messages in this direction (not reset when the key is replaced).
+    messages: u64,
+    bytes_with_key: u64,
+    messages_with_key: u64,
+    key_created: Instant,
+}
+
+impl EncryptedDirection {
+    fn new(key: [u8; 32]) -> EncryptedDirection {
+        EncryptedDirection {
+            key,
+            cipher: CipherType::new((&key).into()),
+            messages: 0,
+            bytes_with_key: 0,
+            messages_with_key: 0,
+            key_created: Instant::now(),
+        }
+    }
+
+    /// Replaces the key with the next one (see `key_exchange::next_key`).
+    fn rekey(&mut self) {
+        let messages = self.messages;
+        *self = EncryptedDirection::new(next_key(&self.key));
+        self.messages = messages;
+    }
+
+    fn exceeds(&self, limits: &RekeyLimits) -> bool {
+        self.bytes_with_key >= limits.bytes
+            || self.messages_with_key >= limits.messages
+            || self.key_created.elapsed() >= limits.interval
+    }
+}
+
 enum QuickTransferStreamOption {
     Unencrypted,
-    Encrypted { cipher: Box<CipherType> },
+    /// Each direction has its own key and numbers its messages from 0 (the number is
+    /// authenticated, so replayed, dropped or reordered messages are detected).
+    /// The sending side replaces its key after `limits` are reached (see `send_tcp`).
+    Encrypted {
+        sending: Box<EncryptedDirection>,
+        receiving: Box<EncryptedDirection>,
+        limits: RekeyLimits,
+    },
 }
 
+/// A connection over which messages are exchanged (a TCP stream or a TLS stream over it).
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
 pub struct QuickTransferStream {
     option: QuickTransferStreamOption,
-    stream: TcpStream,
+    // Buffered, so that an arriving message can be waited for without receiving it:
+    stream: BufReader<Box<dyn Transport>>,
     role: ProgramRole,
     timeout: u16,
 }
 
 impl QuickTransferStream {
     pub fn new_unencrypted(
-        stream: TcpStream,
+        stream: impl Transport + 'static,
         role: ProgramRole,
         timeout: u16,
     ) -> QuickTransferStream {
         QuickTransferStream {
             option: QuickTransferStreamOption::Unencrypted,
-            stream,
+            stream: BufReader::new(Box::new(stream)),
             role,
             timeout,
         }
     }
+    // Encrypted connections are started with a key exchange (see `change_to_encrypted`):
+    #[cfg(test)]
     pub fn new_encrypted(
-        stream: TcpStream,
-        cipher: CipherType,
+        stream: impl Transport + 'static,
+        session_keys: &SessionKeys,
+        limits: RekeyLimits,
         role: ProgramRole,
         timeout: u16,
     ) -> QuickTransferStream {
-        QuickTransferStream {
-            option: QuickTransferStreamOption::Encrypted {
-                cipher: Box::new(cipher),
-            },
-            stream,
-            role,
-            timeout,
-        }
+        let mut stream = QuickTransferStream::new_unencrypted(stream, role, timeout);
+        stream.change_to_encrypted(session_keys, limits);
+
+        stream
     }
-    pub fn change_to_encrypted(&mut self, cipher: CipherType) {
+    pub fn change_to_encrypted(&mut self, session_keys: &SessionKeys, limits: RekeyLimits) {
         self.option = QuickTransferStreamOption::Encrypted {
-            cipher: Box::new(cipher),
+            sending: Box::new(EncryptedDirection::new(session_keys.sending)),
+            receiving: Box::new(EncryptedDirection::new(session_keys.receiving)),
+            limits,
         };
     }
 }
@@ -135,13 +287,242 @@ impl CommunicationAgent<'_> {
     pub async fn receive_bare_message_header(&mut self) -> Result<String, QuickTransferError> {
         self.stream.receive_bare_message_header(self.timeout).await
     }
-    pub fn change_to_encrypted(&mut self, cipher: CipherType) {
-        self.stream.change_to_encrypted(cipher);
+    pub async fn send_bare_data(&mut self, data: &[u8]) -> Result<(), QuickTransferError> {
+        self.stream.send_bare_data(data).await
+    }
+    pub async fn receive_bare_data(&mut self, buffer: &mut [u8]) -> Result<(), QuickTransferError> {
+        self.stream.receive_bare_data(buffer, self.timeout).await
+    }
+    pub fn change_to_encrypted(&mut self, session_keys: &SessionKeys, limits: RekeyLimits) {
+        self.stream.change_to_encrypted(session_keys, limits);
+    }
+
+    /// Reads a bounded fragment of a file on the server (`file_name` is relative to current view).
+    pub async fn read_remote_file(
+        &mut self,
+        file_name: &str,
+        range: &ReadRange,
+    ) -> Result<ReadAnswer, QuickTransferError> {
+        self.send_read_request(file_name, range).await?;
+
+        let message = self.receive_tcp(false).await?;
+        let message = self.read_message_header_check(&message, MESSAGE_READ_ANSWER)?;
+
+        self.read_answer(message)
+    }
+
+    /// Searches current directory on the server. `on_match` is called for every match as soon as
+    /// it arrives, until `cancel` completes (the server is then asked to stop). Returns the way
+    /// the search has ended.
+    pub async fn find_remote(
+        &mut self,
+        query: &FindQuery,
+        mut on_match: impl FnMut(FindMatch) -> Result<(), QuickTransferError>,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<FindEnd, QuickTransferError> {
+        self.send_find_request(query).await?;
+
+        tokio::pin!(cancel);
+        let mut cancelled = false;
+        loop {
+            if !cancelled {
+                tokio::select! {
+                    result = self.wait_for_message() => result?,
+                    _ = &mut cancel => {
+                        self.send_cancel().await?;
+                        cancelled = true;
+                        continue;
+                    }
+                }
+            }
+
+            match self.receive_find_event().await? {
+                FindEvent::Match(found) => on_match(found)?,
+                FindEvent::End(find_end) => return Ok(find_end),
+            }
+        }
+    }
+
+    /// Uploads a file to current directory on the server. File contents are sent only if
+    /// the server accepts the upload (e.g. it has enough space for the file).
+    pub async fn upload_file(
+        &mut self,
+        file: File,
+        file_size: u64,
+        file_name: &str,
+        file_path: &Path,
+    ) -> Result<UploadResult, QuickTransferError> {
+        self.send_upload_request(file_name, file_size).await?;
+
+        let message = self.receive_tcp(false).await?;
+        let (header, message) = self.read_message_header(&message)?;
+        match header.as_str() {
+            MESSAGE_UPLOAD_ACCEPT => {}
+            MESSAGE_UPLOAD_RESULT => return self.read_answer(message),
+            _ => return Err(QuickTransferError::SentInvalidData(self.role)),
+        }
+
+        self.send_file(file, file_size, file_path).await?;
+
+        let message = self.receive_tcp(false).await?;
+        let message = self.read_message_header_check(&message, MESSAGE_UPLOAD_RESULT)?;
+
+        self.read_answer(message)
+    }
+
+    /// Asks the server whether an upload destination already exists with the same size and,
+    /// if so, for its digest (computing it may take long, so no timeout is set).
+    pub async fn check_remote_upload(
+        &mut self,
+        file_name: &str,
+        file_size: u64,
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<UploadCheckAnswer, QuickTransferError> {
+        self.send_upload_check_request(file_name, file_size, algorithm)
+            .await?;
+
+        let message = self.receive_tcp(true).await?;
+        let message = self.read_message_header_check(&message, MESSAGE_UPLOAD_CHECK_ANSWER)?;
+
+        self.read_answer(message)
+    }
+
+    /// Requests disk usage of a file/directory on the server (computing it may take long,
+    /// so no timeout is set).
+    pub async fn disk_usage_remote(
+        &mut self,
+        path: &str,
+        summarize: bool,
+    ) -> Result<DuAnswer, QuickTransferError> {
+        self.send_du_request(path, summarize).await?;
+
+        let message = self.receive_tcp(true).await?;
+        let message = self.read_message_header_check(&message, MESSAGE_DU_ANSWER)?;
+
+        self.read_answer(message)
+    }
+
+    /// Requests free/total space of the filesystem holding the server root directory.
+    pub async fn disk_space_remote(&mut self) -> Result<DfAnswer, QuickTransferError> {
+        self.send_df_request().await?;
+
+        let message = self.receive_tcp(false).await?;
+        let message = self.read_message_header_check(&message, MESSAGE_DF_ANSWER)?;
+
+        self.read_answer(message)
+    }
+
+    /// Requests a checksum of a file on the server. If `cancel` completes before the server
+    /// answers, the computation is cancelled (the answer is then usually `Cancelled`).
+    pub async fn checksum_remote(
+        &mut self,
+        file_name: &str,
+        algorithm: ChecksumAlgorithm,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<ChecksumAnswer, QuickTransferError> {
+        self.send_checksum_request(file_name, algorithm).await?;
+
+        tokio::select! {
+            result = self.wait_for_message() => result?,
+            _ = cancel => self.send_cancel().await?,
+        }
+
+        let message = self.receive_tcp(true).await?;
+        let message = self.read_message_header_check(&message, MESSAGE_CHECKSUM_ANSWER)?;
+
+        self.read_answer(message)
+    }
+
+    /// Copies a file/directory on the server. `on_progress` is called for every progress report
+    /// of a long copy. `flags` are a combination of `COPY_RECURSIVE` and `COPY_OVERWRITE`.
+    pub async fn copy_remote(
+        &mut self,
+        source: &str,
+        destination: &str,
+        flags: u64,
+        mut on_progress: impl FnMut(CopyProgress) -> Result<(), QuickTransferError>,
+    ) -> Result<CopyAnswer, QuickTransferError> {
+        self.send_copy_request(source, destination, flags).await?;
+
+        loop {
+            match self.receive_copy_event().await? {
+                CopyEvent::Progress(progress) => on_progress(progress)?,
+                CopyEvent::End(copy_answer) => return Ok(copy_answer),
+            }
+        }
+    }
+
+    /// Follows a file on the server from byte `offset`: `on_update` is called for every change of
+    /// the file until `cancel` completes (the server is then asked to stop) or following fails.
+    pub async fn follow_remote_file(
+        &mut self,
+        file_name: &str,
+        offset: u64,
+        mut on_update: impl FnMut(FollowUpdate) -> Result<(), QuickTransferError>,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<FollowEnd, QuickTransferError> {
+        self.send_follow_request(file_name, offset).await?;
+
+        tokio::pin!(cancel);
+        let mut cancelled = false;
+        loop {
+            if !cancelled {
+                tokio::select! {
+                    result = self.wait_for_message() => result?,
+                    _ = &mut cancel => {
+                        self.send_cancel().await?;
+                        cancelled = true;
+                        continue;
+                    }
+                }
+            }
+
+            match self.receive_follow_event().await? {
+                FollowEvent::Update(update) => on_update(update)?,
+                FollowEvent::End(follow_end) => return Ok(follow_end),
+            }
+        }
+    }
+
+    /// Requests metadata of a file/directory on the server (symbolic links are not followed).
+    pub async fn stat_remote_file(
+        &mut self,
+        file_name: &str,
+    ) -> Result<StatAnswer, QuickTransferError> {
+        self.send_stat_request(file_name).await?;
+
+        let message = self.receive_tcp(false).await?;
+        let message = self.read_message_header_check(&message, MESSAGE_STAT_ANSWER)?;
+
+        self.read_answer(message)
     }
 }
 
 // Helper functions:
 
+/// Removes the files from a directory description (for clients that may not list directories).
+pub fn without_contents(directory_contents: MessageDirectoryContents) -> MessageDirectoryContents {
+    match directory_contents {
+        MessageDirectoryContents::Success(contents) => {
+            MessageDirectoryContents::Success(DirectoryContents {
+                location: contents.location,
+                positions: vec![],
+            })
+        }
+        error => error,
+    }
+}
+
+/// Describes how a connection is protected (printed once it is established).
+pub fn connection_description(tls: bool, encrypted: bool) -> &'static str {
+    match (tls, encrypted) {
+        (true, true) => "over TLS, encrypted",
+        (true, false) => "over TLS",
+        (false, true) => "encrypted",
+        (false, false) => "not encrypted",
+    }
+}
+
 /// Receives name of the opposite side computer name.
 fn read_opposite_role(role: &ProgramRole, capitalize: bool) -> &'static str {
     if let ProgramRole::Server = role {
@@ -161,15 +542,9 @@ fn read_opposite_role(role: &ProgramRole, capitalize: bool) -> &'static str {
 pub fn directory_description(
     directory_path: &Path,
     root_directory_path: &Path,
+    symlink_policy: SymlinkPolicy,
 ) -> Result<MessageDirectoryContents, QuickTransferError> {
-    let root = root_directory_path.to_str().unwrap();
-    let mut path_displayed =
-        String::from(directory_path.to_str().unwrap().strip_prefix(root).unwrap());
-
-    if root == "/" && !path_displayed.is_empty() {
-        path_displayed.insert(0, '/');
-    }
-    path_displayed.insert(0, '.');
+    let path_displayed = displayed_location(directory_path, root_directory_path);
 
     let paths =
         fs::read_dir(directory_path).map_err(|_| QuickTransferError::ReadingDirectoryContents)?;
@@ -207,9 +582,12 @@ pub fn directory_description(
                     file_name = file_name.strip_prefix("\\").unwrap_or(file_name);
                 }
 
+                // A symbolic link is a directory only if it may be followed to one:
                 DirectoryPosition {
                     name: String::from(file_name.strip_prefix("./").unwrap_or(file_name)),
-                    is_directory: path.is_dir(),
+                    is_directory: path.is_dir()
+                        && (!path.is_symlink()
+                            || symlink_policy.allows_following(&path, root_directory_path)),
                 }
             })
             .collect(),
@@ -222,6 +600,185 @@ pub fn directory_description(
     Ok(directory_contents)
 }
 
+/// Creates a description of directory contents including metadata of every position
+/// (positions whose metadata cannot be read are skipped).
+pub fn detailed_directory_description(
+    directory_path: &Path,
+    root_directory_path: &Path,
+    symlink_policy: SymlinkPolicy,
+) -> MessageDetailedDirectoryContents {
+    let Ok(entries) = fs::read_dir(directory_path) else {
+        return MessageDetailedDirectoryContents::ReadingDirectoryError;
+    };
+    let positions = entries
+        .filter_map(|entry| {
+            file_metadata(&entry.ok()?.path(), root_directory_path, symlink_policy).ok()
+        })
+        .collect();
+
+    MessageDetailedDirectoryContents::Success(DetailedDirectoryContents {
+        location: displayed_location(directory_path, root_directory_path),
+        positions,
+    })
+}
+
+/// Returns the location of a directory displayed to client (relative to the root directory).
+fn displayed_location(directory_path: &Path, root_directory_path: &Path) -> String {
+    let root = root_directory_path.to_str().unwrap();
+    let mut path_displayed =
+        String::from(directory_path.to_str().unwrap().strip_prefix(root).unwrap());
+
+    if root == "/" && !path_displayed.is_empty() {
+        path_displayed.insert(0, '/');
+    }
+    path_displayed.insert(0, '.');
+
+    path_displayed
+}
+
+/// Returns metadata of a file/directory that can be sent (symbolic links are not followed and
+/// their targets are sent only if `symlink_policy` allows showing them).
+pub fn file_metadata(
+    path: &Path,
+    root_directory_path: &Path,
+    symlink_policy: SymlinkPolicy,
+) -> io::Result<FileMetadata> {
+    let metadata = fs::symlink_metadata(path)?;
+
+    let file_type = if metadata.is_symlink() {
+        FileType::Symlink
+    } else if metadata.is_dir() {
+        FileType::Directory
+    } else if metadata.is_file() {
+        FileType::File
+    } else {
+        FileType::Other
+    };
+
+    let symlink_target = if matches!(file_type, FileType::Symlink)
+        && symlink_policy.allows_showing_target(path, root_directory_path)
+    {
+        fs::read_link(path)
+            .ok()
+            .map(|target| target.to_string_lossy().into_owned())
+    } else {
+        None
+    };
+
+    Ok(FileMetadata {
+        name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from(".")),
+        file_type,
+        size: metadata.len(),
+        mode: file_mode(&metadata),
+        read_only: metadata.permissions().readonly(),
+        owner: file_owner(&metadata),
+        modified: unix_time(metadata.modified()),
+        accessed: unix_time(metadata.accessed()),
+        created: unix_time(metadata.created()),
+        symlink_target,
+    })
+}
+
+/// Converts a file time to seconds since the Unix epoch (if the platform supports it).
+fn unix_time(time: io::Result<SystemTime>) -> Option<i64> {
+    let time = time.ok()?;
+
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs().try_into().ok(),
+        Err(error) => i64::try_from(error.duration().as_secs())
+            .ok()
+            .map(|secs| -secs),
+    }
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(metadata.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn file_owner(metadata: &Metadata) -> Option<FileOwner> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(FileOwner {
+        user_id: metadata.uid(),
+        group_id: metadata.gid(),
+        user_name: user_name(metadata.uid()),
+        group_name: group_name(metadata.gid()),
+    })
+}
+
+#[cfg(not(unix))]
+fn file_owner(_metadata: &Metadata) -> Option<FileOwner> {
+    None
+}
+
+/// Looks up a name of the user with id `user_id`.
+#[cfg(unix)]
+fn user_name(user_id: u32) -> Option<String> {
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    // SAFETY: `passwd` is plain old data, for which all-zero bytes are a valid value.
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+
+    // SAFETY: all pointers are valid for the duration of the call and `buffer.len()` is the
+    // size of `buffer`.
+    let status = unsafe {
+        libc::getpwuid_r(
+            user_id,
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    if status != 0 || result.is_null() {
+        return None;
+    }
+
+    // SAFETY: on success `pw_name` points to a NUL-terminated string stored in `buffer`.
+    let name = unsafe { std::ffi::CStr::from_ptr(passwd.pw_name) };
+    name.to_str().ok().map(String::from)
+}
+
+/// Looks up a name of the group with id `group_id`.
+#[cfg(unix)]
+fn group_name(group_id: u32) -> Option<String> {
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    // SAFETY: `group` is plain old data, for which all-zero bytes are a valid value.
+    let mut group: libc::group = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+
+    // SAFETY: all pointers are valid for the duration of the call and `buffer.len()` is the
+    // size of `buffer`.
+    let status = unsafe {
+        libc::getgrgid_r(
+            group_id,
+            &mut group,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    if status != 0 || result.is_null() {
+        return None;
+    }
+
+    // SAFETY: on success `gr_name` points to a NUL-terminated string stored in `buffer`.
+    let name = unsafe { std::ffi::CStr::from_ptr(group.gr_name) };
+    name.to_str().ok().map(String::from)
+}
+
 /// Custom error enum.
 #[derive(Error, Debug)]
 pub enum QuickTransferError {
@@ -273,12 +830,52 @@ pub enum QuickTransferError {
     #[error("Server doesn't support encryption.")]
     ServerDoesNotSupportEncryption,
 
-    #[error("An error occurred while deciphering. Make sure that client and server use the same AES256 key.")]
+    #[error("Server doesn't support encryption with a passphrase.")]
+    ServerDoesNotSupportPassphrase,
+
+    #[error("The connection is not encrypted, but the server requires encryption. Run the client with a key (`-k`) or a passphrase (`--passphrase`).")]
+    EncryptionRequired,
+
+    #[error("Authentication failed. Make sure that client and server use the same AES256 key or passphrase.")]
+    AuthenticationFailed,
+
+    #[error(
+        "The TLS handshake failed. Make sure that both client and server are run with `--tls`."
+    )]
+    TlsHandshake,
+
+    #[error("The certificate of server `{server}` does not match the pinned fingerprint. The connection may have been intercepted. If the server's certificate was regenerated, remove its entry from `{known_servers}`.")]
+    CertificateMismatch {
+        server: String,
+        known_servers: String,
+    },
+
+    #[error(
+        "A problem with loading or generating the TLS certificate in `{directory}` has occurred."
+    )]
+    TlsCertificate { directory: String },
+
+    #[error("Server requires logging in. Run the client with a user name (`-u`).")]
+    LoginRequired,
+
+    #[error("Logging in failed. Make sure that the user name and password are correct.")]
+    LoginFailed,
+
+    #[error("Server doesn't have user accounts. Run the client without a user name.")]
+    ServerDoesNotSupportLogin,
+
+    #[error("You don't have permission to {} on this server.", .0.description())]
+    PermissionDenied(Permission),
+
+    #[error("An error occurred while deciphering. Make sure that client and server use the same AES256 key or passphrase.")]
     Deciphering,
 
     #[error("An error occurred while ciphering.")]
     Ciphering,
 
+    #[error("A message from {} was replayed or arrived out of order. The connection may have been tampered with.", read_opposite_role(.0, false))]
+    UnexpectedMessageNumber(ProgramRole),
+
     #[error("")]
     Other,
 }
@@ -291,10 +888,11 @@ mod test {
     use io::Cursor;
     use tokio::{
         io::{AsyncReadExt, AsyncWriteExt},
-        net::TcpListener,
+        net::{TcpListener, TcpStream},
     };
 
     use super::*;
+    use messages::{MAX_FILE_FRAGMENT_SIZE, MESSAGE_CANCEL};
 
     #[tokio::test]
     async fn test_send_bare_message() {
@@ -361,9 +959,11 @@ mod test {
             DEFAULT_TIMEOUT,
         );
 
-        let key = GenericArray::from_slice(&[0u8; 32]);
-        let cipher = AesGcm::new(key);
-        agent.change_to_encrypted(cipher);
+        let session_keys = SessionKeys {
+            sending: [0u8; 32],
+            receiving: [0u8; 32],
+        };
+        agent.change_to_encrypted(&session_keys, RekeyLimits::default());
 
         if let QuickTransferStreamOption::Encrypted { .. } = agent.stream.option {
             // Test passed
@@ -388,11 +988,14 @@ mod test {
         });
 
         let stream = TcpStream::connect(addr).await.unwrap();
-        let key = GenericArray::from_slice(&[0u8; 32]);
-        let cipher = Aes256Gcm::new(key);
+        let session_keys = SessionKeys {
+            sending: [0u8; 32],
+            receiving: [0u8; 32],
+        };
         let mut quick_transfer_stream = QuickTransferStream::new_encrypted(
             stream,
-            cipher,
+            &session_keys,
+            RekeyLimits::default(),
             ProgramRole::Client,
             DEFAULT_TIMEOUT,
         );
@@ -452,4 +1055,267 @@ mod test {
 
         assert_eq!(received_text.unwrap(), test_str.as_bytes());
     }
+
+    #[tokio::test]
+    async fn test_replayed_encrypted_message() {
+        let listener = TcpListener::bind("::1:9995").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut length = [0_u8; 8];
+            socket.read_exact(&mut length).await.unwrap();
+            let mut frame = vec![0_u8; u64::from_be_bytes(length).try_into().unwrap()];
+            socket.read_exact(&mut frame).await.unwrap();
+
+            // Send the received frame back twice:
+            for _ in 0..2 {
+                socket.write_all(&length).await.unwrap();
+                socket.write_all(&frame).await.unwrap();
+            }
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        // The same key in both directions, so that only message numbers reveal the replay:
+        let session_keys = SessionKeys {
+            sending: [0u8; 32],
+            receiving: [0u8; 32],
+        };
+        let mut quick_transfer_stream = QuickTransferStream::new_encrypted(
+            stream,
+            &session_keys,
+            RekeyLimits::default(),
+            ProgramRole::Client,
+            DEFAULT_TIMEOUT,
+        );
+        let mut agent = CommunicationAgent::new(
+            &mut quick_transfer_stream,
+            ProgramRole::Client,
+            DEFAULT_TIMEOUT,
+        );
+
+        agent.send_cancel().await.unwrap();
+        assert!(agent.receive_tcp(false).await.is_ok());
+        assert!(matches!(
+            agent.receive_tcp(false).await,
+            Err(QuickTransferError::UnexpectedMessageNumber(
+                ProgramRole::Client
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rekeying() {
+        let listener = TcpListener::bind("::1:9996").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let session_keys = SessionKeys {
+            sending: [1u8; 32],
+            receiving: [2u8; 32],
+        };
+        // The key is replaced after every 2 messages:
+        let limits = RekeyLimits {
+            messages: 2,
+            ..RekeyLimits::default()
+        };
+
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let server_keys = SessionKeys {
+                sending: [2u8; 32],
+                receiving: [1u8; 32],
+            };
+            let mut quick_transfer_stream = QuickTransferStream::new_encrypted(
+                socket,
+                &server_keys,
+                limits,
+                ProgramRole::Server,
+                DEFAULT_TIMEOUT,
+            );
+            let mut agent = CommunicationAgent::new(
+                &mut quick_transfer_stream,
+                ProgramRole::Server,
+                DEFAULT_TIMEOUT,
+            );
+
+            for _ in 0..5 {
+                let message = agent.receive_tcp(false).await.unwrap();
+                agent
+                    .read_message_header_check(&message, MESSAGE_CANCEL)
+                    .unwrap();
+            }
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut quick_transfer_stream = QuickTransferStream::new_encrypted(
+            stream,
+            &session_keys,
+            limits,
+            ProgramRole::Client,
+            DEFAULT_TIMEOUT,
+        );
+        let mut agent = CommunicationAgent::new(
+            &mut quick_transfer_stream,
+            ProgramRole::Client,
+            DEFAULT_TIMEOUT,
+        );
+        for _ in 0..5 {
+            agent.send_cancel().await.unwrap();
+        }
+        server.await.unwrap();
+
+        let QuickTransferStreamOption::Encrypted { sending, .. } = &agent.stream.option else {
+            panic!("Stream is not encrypted");
+        };
+        assert_eq!(sending.key, next_key(&next_key(&[1u8; 32])));
+        // Rekey messages are numbered too:
+        assert_eq!(sending.messages, 7);
+    }
+
+    #[tokio::test]
+    async fn test_file_looking_like_rekey() {
+        let listener = TcpListener::bind("::1:9997").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let directory = std::env::temp_dir().join("quick_transfer_test_file_looking_like_rekey");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        // A file (and fragments of a longer file) looking like the old "REKEY" message:
+        let files = [
+            b"REKEY___".to_vec(),
+            b"REKEY___".repeat(MAX_FILE_FRAGMENT_SIZE / 8 * 3),
+        ];
+        let server_files = files.clone();
+        let limits = RekeyLimits {
+            messages: 2,
+            ..RekeyLimits::default()
+        };
+
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let server_keys = SessionKeys {
+                sending: [2u8; 32],
+                receiving: [1u8; 32],
+            };
+            let mut quick_transfer_stream = QuickTransferStream::new_encrypted(
+                socket,
+                &server_keys,
+                limits,
+                ProgramRole::Server,
+                DEFAULT_TIMEOUT,
+            );
+            let mut agent = CommunicationAgent::new(
+                &mut quick_transfer_stream,
+                ProgramRole::Server,
+                DEFAULT_TIMEOUT,
+            );
+
+            for contents in server_files {
+                let mut received = vec![];
+                assert!(agent
+                    .receive_file(
+                        &mut received,
+                        contents.len() as u64,
+                        Path::new("rekey.bin"),
+                        false
+                    )
+                    .await
+                    .unwrap());
+                assert_eq!(received, contents);
+            }
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let session_keys = SessionKeys {
+            sending: [1u8; 32],
+            receiving: [2u8; 32],
+        };
+        let mut quick_transfer_stream = QuickTransferStream::new_encrypted(
+            stream,
+            &session_keys,
+            limits,
+            ProgramRole::Client,
+            DEFAULT_TIMEOUT,
+        );
+        let mut agent = CommunicationAgent::new(
+            &mut quick_transfer_stream,
+            ProgramRole::Client,
+            DEFAULT_TIMEOUT,
+        );
+        let file_path = directory.join("rekey.bin");
+        for contents in files {
+            fs::write(&file_path, &contents).unwrap();
+            agent
+                .send_file(
+                    File::open(&file_path).unwrap(),
+                    contents.len() as u64,
+                    &file_path,
+                )
+                .await
+                .unwrap();
+        }
+        server.await.unwrap();
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_file_metadata() {
+        let directory = std::env::temp_dir().join("quick_transfer_test_file_metadata");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("file.txt"), b"QuickTransfer").unwrap();
+        let directory = directory.canonicalize().unwrap();
+        let policy = SymlinkPolicy::FollowWithinRoot;
+
+        let metadata = file_metadata(&directory.join("file.txt"), &directory, policy).unwrap();
+        assert_eq!(metadata.name, "file.txt");
+        assert_eq!(metadata.file_type, FileType::File);
+        assert_eq!(metadata.size, 13);
+        assert!(metadata.modified.is_some());
+        assert_eq!(metadata.symlink_target, None);
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("file.txt", directory.join("link")).unwrap();
+            let metadata = file_metadata(&directory.join("link"), &directory, policy).unwrap();
+            assert_eq!(metadata.file_type, FileType::Symlink);
+            assert_eq!(metadata.symlink_target.as_deref(), Some("file.txt"));
+
+            // Targets outside the root directory are shown only if links may be followed there:
+            std::os::unix::fs::symlink("/", directory.join("root_link")).unwrap();
+            let target = |policy| {
+                file_metadata(&directory.join("root_link"), &directory, policy)
+                    .unwrap()
+                    .symlink_target
+            };
+            assert_eq!(target(SymlinkPolicy::FollowWithinRoot), None);
+            assert_eq!(target(SymlinkPolicy::FollowAll).as_deref(), Some("/"));
+            let metadata =
+                file_metadata(&directory.join("link"), &directory, SymlinkPolicy::Deny).unwrap();
+            assert_eq!(metadata.symlink_target, None);
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_detailed_directory_description() {
+        let root = std::env::temp_dir().join("quick_transfer_test_detailed_description");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::write(root.join("nested/file.txt"), b"QuickTransfer").unwrap();
+
+        let MessageDetailedDirectoryContents::Success(contents) = detailed_directory_description(
+            &root.join("nested"),
+            &root,
+            SymlinkPolicy::FollowWithinRoot,
+        ) else {
+            panic!("Reading directory contents failed");
+        };
+        assert_eq!(contents.location, "./nested");
+        assert_eq!(contents.positions.len(), 1);
+        assert_eq!(contents.positions[0].name, "file.txt");
+        assert_eq!(contents.positions[0].size, 13);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
 }
@@ -0,0 +1,59 @@
+use argon2::Argon2;
+use rand::{rngs::OsRng, RngCore};
+use tokio::task;
+
+use crate::common::QuickTransferError;
+
+/// Length of a salt sent by client in "INIT_PWD" message.
+pub const SALT_LENGTH: usize = 16;
+
+/// Environment variable from which the passphrase is taken (if set).
+pub const PASSPHRASE_VARIABLE: &str = "QUICKTRANSFER_PASSPHRASE";
+
+/// Generates a random salt for a new connection.
+pub fn random_salt() -> [u8; SALT_LENGTH] {
+    let mut salt = [0_u8; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+
+    salt
+}
+
+/// Derives an AES256 key from a passphrase with Argon2id (default parameters).
+pub fn derive_key(passphrase: &str, salt: &[u8; SALT_LENGTH]) -> Option<[u8; 32]> {
+    let mut key = [0_u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .ok()?;
+
+    Some(key)
+}
+
+/// Derives the key in a separate thread (the derivation is deliberately slow).
+pub async fn spawn_derive_key(
+    passphrase: String,
+    salt: [u8; SALT_LENGTH],
+) -> Result<[u8; 32], QuickTransferError> {
+    task::spawn_blocking(move || derive_key(&passphrase, &salt))
+        .await
+        .ok()
+        .flatten()
+        .ok_or(QuickTransferError::Fatal)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_derive_key() {
+        let salt = [7_u8; SALT_LENGTH];
+        let key = derive_key("correct horse", &salt).unwrap();
+
+        assert_eq!(key, derive_key("correct horse", &salt).unwrap());
+        assert_ne!(
+            key,
+            derive_key("correct horse", &[8_u8; SALT_LENGTH]).unwrap()
+        );
+        assert_ne!(key, derive_key("wrong horse", &salt).unwrap());
+    }
+}
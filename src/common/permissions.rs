@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+
+/// An operation that a client may be permitted to perform on the server.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+pub enum Permission {
+    /// Listing directories and reading details of files (`ls`, `find`, `stat`, `du`).
+    List,
+    /// Reading files (`download`, `cat`, `tail -f`, `checksum`).
+    Download,
+    /// Creating files (`upload`, `cp`).
+    Upload,
+    /// Replacing existing files (by `upload`, `cp -f` or `mv -f`).
+    Overwrite,
+    /// Creating directories (`mkdir`, `cp -r` of a directory).
+    Mkdir,
+    /// Renaming and moving files and directories (`mv`).
+    Rename,
+    /// Removing files and directories (`rm`).
+    Delete,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 7] = [
+        Permission::List,
+        Permission::Download,
+        Permission::Upload,
+        Permission::Overwrite,
+        Permission::Mkdir,
+        Permission::Rename,
+        Permission::Delete,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Permission::List => "list",
+            Permission::Download => "download",
+            Permission::Upload => "upload",
+            Permission::Overwrite => "overwrite",
+            Permission::Mkdir => "mkdir",
+            Permission::Rename => "rename",
+            Permission::Delete => "delete",
+        }
+    }
+
+    /// Describes what the permission allows (used in messages about denials).
+    pub fn description(self) -> &'static str {
+        match self {
+            Permission::List => "list directories",
+            Permission::Download => "download files",
+            Permission::Upload => "upload files",
+            Permission::Overwrite => "overwrite files",
+            Permission::Mkdir => "create directories",
+            Permission::Rename => "rename or move files",
+            Permission::Delete => "delete files",
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// A set of permissions of a client.
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+pub struct Permissions(u8);
+
+impl Permissions {
+    pub const FULL: Permissions = Permissions(0b111_1111);
+    pub const READ_ONLY: Permissions = Permissions(0b11);
+    /// Creating new files and directories, without reading files or changing existing ones.
+    pub const UPLOAD_ONLY: Permissions = Permissions(0b1_0101);
+
+    pub fn allows(self, permission: Permission) -> bool {
+        self.0 & permission.bit() != 0
+    }
+
+    /// Parses `full`, `read-only`, `upload-only` or a comma-separated list of permission names
+    /// (e.g. `list,download,upload`).
+    pub fn from_spec(spec: &str) -> Option<Permissions> {
+        match spec {
+            "full" => Some(Permissions::FULL),
+            "read-only" => Some(Permissions::READ_ONLY),
+            "upload-only" => Some(Permissions::UPLOAD_ONLY),
+            _ => spec
+                .split(',')
+                .map(|name| {
+                    Permission::ALL
+                        .into_iter()
+                        .find(|permission| permission.name() == name.trim())
+                })
+                .try_fold(Permissions(0), |permissions, permission| {
+                    Some(Permissions(permissions.0 | permission?.bit()))
+                }),
+        }
+    }
+
+    /// Names of the permissions separated with commas.
+    pub fn names(self) -> String {
+        let names: Vec<&str> = Permission::ALL
+            .into_iter()
+            .filter(|&permission| self.allows(permission))
+            .map(Permission::name)
+            .collect();
+
+        if names.is_empty() {
+            String::from("none")
+        } else {
+            names.join(", ")
+        }
+    }
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Permissions::FULL
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_permissions_from_spec() {
+        let read_only = Permissions::from_spec("read-only").unwrap();
+        assert!(read_only.allows(Permission::List));
+        assert!(read_only.allows(Permission::Download));
+        assert!(!read_only.allows(Permission::Upload));
+        assert_eq!(Permissions::from_spec("download, list"), Some(read_only));
+
+        let upload_only = Permissions::from_spec("upload-only").unwrap();
+        assert_eq!(upload_only.names(), "list, upload, mkdir");
+        assert!(!upload_only.allows(Permission::Overwrite));
+
+        assert_eq!(Permissions::FULL.names().split(", ").count(), 7);
+        assert_eq!(Permissions::from_spec("list,erase"), None);
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Default number of connections accepted from one address per minute.
+pub const DEFAULT_CONNECTIONS_PER_MINUTE: u32 = 120;
+
+/// Default number of failed authentications after which an address is locked out.
+pub const DEFAULT_MAX_FAILURES: u32 = 5;
+
+/// Default time for which an address is locked out.
+pub const DEFAULT_LOCKOUT_SECONDS: u64 = 300;
+
+/// Length of the window in which connections from one address are counted.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Time after the last failed authentication of an address after which its failures are forgotten.
+const FAILURE_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// Number of tracked addresses above which records that do not matter anymore are dropped.
+const PRUNED_RECORDS: usize = 1024;
+
+/// Thresholds of handshake rate limiting (0 turns a limit off).
+#[derive(Copy, Clone, Debug)]
+pub struct RateLimits {
+    pub connections_per_minute: u32,
+    pub max_failures: u32,
+    pub lockout: Duration,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            connections_per_minute: DEFAULT_CONNECTIONS_PER_MINUTE,
+            max_failures: DEFAULT_MAX_FAILURES,
+            lockout: Duration::from_secs(DEFAULT_LOCKOUT_SECONDS),
+        }
+    }
+}
+
+/// Whether a new connection from an address may be handled.
+#[derive(PartialEq, Debug)]
+pub enum Admission {
+    Allowed,
+    /// Too many connections in the current window. `first` is set for the first rejected
+    /// connection of the window (so that it is logged only once).
+    RateLimited {
+        first: bool,
+    },
+    LockedOut,
+}
+
+#[derive(Default)]
+struct AddressRecord {
+    window_start: Option<Instant>,
+    connections: u32,
+    failures: u32,
+    last_failure: Option<Instant>,
+    locked_until: Option<Instant>,
+}
+
+impl AddressRecord {
+    /// Whether failed authentications of the address are still counted.
+    fn failures_counted(&self, now: Instant) -> bool {
+        self.failures > 0
+            && self
+                .last_failure
+                .is_some_and(|last_failure| now.duration_since(last_failure) < FAILURE_WINDOW)
+    }
+}
+
+/// Tracks connections and failed authentications of every address (shared by all clients).
+pub struct HandshakeLimiter {
+    limits: RateLimits,
+    records: Mutex<HashMap<IpAddr, AddressRecord>>,
+}
+
+impl HandshakeLimiter {
+    pub fn new(limits: RateLimits) -> HandshakeLimiter {
+        HandshakeLimiter {
+            limits,
+            records: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a new connection from `address` and decides whether it may be handled.
+    pub fn admit(&self, address: IpAddr, now: Instant) -> Admission {
+        let mut records = self.records.lock().unwrap();
+        if records.len() > PRUNED_RECORDS {
+            records.retain(|_, record| {
+                record.locked_until.is_some_and(|until| until > now)
+                    || record.failures_counted(now)
+                    || record
+                        .window_start
+                        .is_some_and(|start| now.duration_since(start) < RATE_WINDOW)
+            });
+        }
+
+        let record = records.entry(address.to_canonical()).or_default();
+        if record.locked_until.is_some_and(|until| until > now) {
+            return Admission::LockedOut;
+        }
+
+        if self.limits.connections_per_minute == 0 {
+            return Admission::Allowed;
+        }
+        if record
+            .window_start
+            .is_none_or(|start| now.duration_since(start) >= RATE_WINDOW)
+        {
+            record.window_start = Some(now);
+            record.connections = 0;
+        }
+        record.connections = record.connections.saturating_add(1);
+
+        if record.connections <= self.limits.connections_per_minute {
+            Admission::Allowed
+        } else {
+            Admission::RateLimited {
+                first: record.connections == self.limits.connections_per_minute + 1,
+            }
+        }
+    }
+
+    /// Counts a failed authentication (failures older than `FAILURE_WINDOW` are forgotten).
+    /// Returns whether the address has just been locked out.
+    pub fn record_failure(&self, address: IpAddr, now: Instant) -> bool {
+        if self.limits.max_failures == 0 {
+            return false;
+        }
+
+        let mut records = self.records.lock().unwrap();
+        let record = records.entry(address.to_canonical()).or_default();
+        if !record.failures_counted(now) {
+            record.failures = 0;
+        }
+        record.failures += 1;
+        record.last_failure = Some(now);
+        if record.failures < self.limits.max_failures {
+            return false;
+        }
+
+        record.failures = 0;
+        record.locked_until = Some(now + self.limits.lockout);
+
+        true
+    }
+
+    /// Forgets failed authentications of an address after it has authenticated.
+    pub fn record_success(&self, address: IpAddr) {
+        if let Some(record) = self
+            .records
+            .lock()
+            .unwrap()
+            .get_mut(&address.to_canonical())
+        {
+            record.failures = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_handshake_limiter() {
+        let limiter = HandshakeLimiter::new(RateLimits {
+            connections_per_minute: 2,
+            max_failures: 2,
+            lockout: Duration::from_secs(30),
+        });
+        let address: IpAddr = "10.0.0.1".parse().unwrap();
+        let other_address: IpAddr = "10.0.0.2".parse().unwrap();
+        let now = Instant::now();
+
+        assert_eq!(limiter.admit(address, now), Admission::Allowed);
+        assert_eq!(limiter.admit(address, now), Admission::Allowed);
+        assert_eq!(
+            limiter.admit(address, now),
+            Admission::RateLimited { first: true }
+        );
+        assert_eq!(
+            limiter.admit(address, now),
+            Admission::RateLimited { first: false }
+        );
+        assert_eq!(limiter.admit(other_address, now), Admission::Allowed);
+        assert_eq!(
+            limiter.admit(address, now + RATE_WINDOW),
+            Admission::Allowed
+        );
+
+        assert!(!limiter.record_failure(other_address, now));
+        limiter.record_success(other_address);
+        assert!(!limiter.record_failure(other_address, now));
+        let later = now + FAILURE_WINDOW;
+        assert!(!limiter.record_failure(other_address, later));
+        assert!(limiter.record_failure(other_address, later));
+        assert_eq!(
+            limiter.admit(other_address, later + Duration::from_secs(10)),
+            Admission::LockedOut
+        );
+        assert_eq!(
+            limiter.admit(other_address, later + Duration::from_secs(31)),
+            Admission::Allowed
+        );
+
+        for i in 0..=PRUNED_RECORDS as u32 {
+            let address = IpAddr::from([10, 1, (i >> 8) as u8, i as u8]);
+            limiter.record_failure(address, now);
+        }
+        limiter.admit(address, later + FAILURE_WINDOW);
+        assert!(limiter.records.lock().unwrap().len() <= 2);
+    }
+}
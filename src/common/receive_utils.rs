@@ -1,24 +1,96 @@
-use aes_gcm::{aead::Aead, Nonce};
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Nonce,
+};
 use byteorder::{ReadBytesExt, BE};
 use core::str;
 use serde::de::DeserializeOwned;
 use std::{
-    fs::File,
     io::{Cursor, Write},
     path::Path,
     time::Duration,
 };
-use tokio::{io::AsyncReadExt, time::timeout};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt},
+    time::timeout,
+};
 
 use crate::common::{
-    map_tcp_error,
-    messages::{EncryptedMessage, UnencryptedMessage, HEADER_NAME_LENGTH, MESSAGE_LENGTH_LENGTH},
+    associated_data, map_tcp_error,
+    messages::{
+        CopyAnswer, CopyProgress, EncryptedMessage, FindEnd, FindMatch, FollowEnd, FollowUpdate,
+        StreamEnd, UnencryptedMessage, HEADER_NAME_LENGTH, MESSAGE_COPY_ANSWER,
+        MESSAGE_COPY_PROGRESS, MESSAGE_DISCONNECT, MESSAGE_FIND_END, MESSAGE_FIND_MATCH,
+        MESSAGE_FOLLOW_END, MESSAGE_FOLLOW_UPDATE, MESSAGE_LENGTH_LENGTH,
+        MESSAGE_PERMISSION_DENIED, MESSAGE_STREAM_DATA, MESSAGE_STREAM_END,
+    },
     CommunicationAgent, QuickTransferError, QuickTransferStream, QuickTransferStreamOption,
 };
 
+/// A message received while a search is in progress.
+pub enum FindEvent {
+    Match(FindMatch),
+    End(FindEnd),
+}
+
+/// A message received while a copy is in progress.
+pub enum CopyEvent {
+    Progress(CopyProgress),
+    End(CopyAnswer),
+}
+
+/// A message received while a file is followed.
+pub enum FollowEvent {
+    Update(FollowUpdate),
+    End(FollowEnd),
+}
+
+/// One fragment of a stream of unknown length.
+pub enum StreamFragment {
+    Data(Vec<u8>),
+    End(StreamEnd),
+}
+
 impl QuickTransferStream {
-    /// Receives one wrapped message from TCP channel.
+    /// Waits (without a timeout) until a message starts arriving, without receiving it.
+    /// Can be safely cancelled (e.g. in `tokio::select!`).
+    async fn wait_for_message(&mut self) -> Result<(), QuickTransferError> {
+        // Bytes filled into the buffer are received later (filling it can be cancelled):
+        let buffered = self
+            .stream
+            .fill_buf()
+            .await
+            .map_err(|error| map_tcp_error(error, self.role))?;
+
+        if buffered.is_empty() {
+            return Err(QuickTransferError::RemoteClosedConnection(self.role));
+        }
+
+        Ok(())
+    }
+
+    /// Receives one wrapped message from TCP channel (a key replacement is handled transparently).
     async fn receive_tcp(&mut self, wait: bool) -> Result<Vec<u8>, QuickTransferError> {
+        let (message, rekey) = self.receive_frame(wait).await?;
+
+        if rekey {
+            if let QuickTransferStreamOption::Encrypted { receiving, .. } = &mut self.option {
+                receiving.rekey();
+            }
+            // The next message is sent right after a rekey message:
+            let (message, rekey) = self.receive_frame(false).await?;
+            if rekey {
+                return Err(QuickTransferError::SentInvalidData(self.role));
+            }
+            return Ok(message);
+        }
+
+        Ok(message)
+    }
+
+    /// Receives one wrapped frame from TCP channel. Returns its contents and whether the key of
+    /// the sending side is replaced after it.
+    async fn receive_frame(&mut self, wait: bool) -> Result<(Vec<u8>, bool), QuickTransferError> {
         let mut message_length_buffer: [u8; 8] = [0_u8; MESSAGE_LENGTH_LENGTH];
         if wait {
             // Read first byte:
@@ -63,19 +135,39 @@ impl QuickTransferStream {
                 let deserialized_message: UnencryptedMessage = bincode::deserialize(&received_data)
                     .map_err(|_| QuickTransferError::SentInvalidData(self.role))?;
 
-                Ok(deserialized_message.content)
+                Ok((deserialized_message.content, false))
             }
-            QuickTransferStreamOption::Encrypted { cipher, .. } => {
+            QuickTransferStreamOption::Encrypted { receiving, .. } => {
                 let deserialized_message: EncryptedMessage =
                     bincode::deserialize(&received_data)
                         .map_err(|_| QuickTransferError::SentInvalidData(self.role))?;
+                if deserialized_message.number != receiving.messages {
+                    return Err(QuickTransferError::UnexpectedMessageNumber(self.role));
+                }
+                if deserialized_message.nonce.len() != 12 {
+                    return Err(QuickTransferError::SentInvalidData(self.role));
+                }
                 let nonce_array = Nonce::from_slice(&deserialized_message.nonce);
 
-                let plain_text = cipher
-                    .decrypt(nonce_array, deserialized_message.content.as_ref())
+                let plain_text = receiving
+                    .cipher
+                    .decrypt(
+                        nonce_array,
+                        Payload {
+                            msg: &deserialized_message.content,
+                            aad: &associated_data(
+                                deserialized_message.number,
+                                deserialized_message.rekey,
+                            ),
+                        },
+                    )
                     .map_err(|_| QuickTransferError::Deciphering)?;
+                receiving.messages += 1;
+                if deserialized_message.rekey && !plain_text.is_empty() {
+                    return Err(QuickTransferError::SentInvalidData(self.role));
+                }
 
-                Ok(plain_text)
+                Ok((plain_text, deserialized_message.rekey))
             }
         }
     }
@@ -85,25 +177,37 @@ impl QuickTransferStream {
         timeout: u16,
     ) -> Result<String, QuickTransferError> {
         let mut message_header_buffer: [u8; 8] = [0_u8; HEADER_NAME_LENGTH];
-
-        let status = self.stream.read_exact(&mut message_header_buffer);
-        match tokio::time::timeout(Duration::from_secs(timeout.into()), status).await {
-            Err(_) => {
-                return Err(QuickTransferError::MessageReceiveTimeout(self.role));
-            }
-            Ok(result) => {
-                result.map_err(|error| map_tcp_error(error, self.role))?;
-            }
-        }
+        self.receive_bare_data(&mut message_header_buffer, timeout)
+            .await?;
 
         let header_received = str::from_utf8(&message_header_buffer)
             .map_err(|_| QuickTransferError::SentInvalidData(self.role))?;
 
         Ok(String::from(header_received))
     }
+
+    /// Receives exactly `buffer.len()` bytes sent directly on TCP stream.
+    pub async fn receive_bare_data(
+        &mut self,
+        buffer: &mut [u8],
+        timeout: u16,
+    ) -> Result<(), QuickTransferError> {
+        let status = self.stream.read_exact(buffer);
+        match tokio::time::timeout(Duration::from_secs(timeout.into()), status).await {
+            Err(_) => Err(QuickTransferError::MessageReceiveTimeout(self.role)),
+            Ok(result) => result
+                .map(|_| ())
+                .map_err(|error| map_tcp_error(error, self.role)),
+        }
+    }
 }
 
 impl CommunicationAgent<'_> {
+    /// Waits until a message starts arriving (see `QuickTransferStream::wait_for_message`).
+    pub async fn wait_for_message(&mut self) -> Result<(), QuickTransferError> {
+        self.stream.wait_for_message().await
+    }
+
     /// Receives one message package.
     /// If wait == true, then timeout for the first byte is not set.
     pub async fn receive_tcp(&mut self, wait: bool) -> Result<Vec<u8>, QuickTransferError> {
@@ -128,7 +232,17 @@ impl CommunicationAgent<'_> {
         &mut self,
         message: &'a [u8],
     ) -> Result<(String, &'a [u8]), QuickTransferError> {
-        self.read_string(message, HEADER_NAME_LENGTH.try_into().unwrap())
+        let (header, message) =
+            self.read_string(message, HEADER_NAME_LENGTH.try_into().unwrap())?;
+
+        // Any request may be denied instead of being answered:
+        if header == MESSAGE_PERMISSION_DENIED {
+            return Err(QuickTransferError::PermissionDenied(
+                self.read_answer(message)?,
+            ));
+        }
+
+        Ok((header, message))
     }
 
     /// Receives (waits) a message header string (takes 8 bytes).
@@ -182,19 +296,87 @@ impl CommunicationAgent<'_> {
         message: &[u8],
     ) -> Result<T, QuickTransferError> {
         let (_, message) = self.read_u64(message)?;
-        let deserialized_answer: T = bincode::deserialize(message).unwrap();
+        let deserialized_answer: T = bincode::deserialize(message)
+            .map_err(|_| QuickTransferError::SentInvalidData(self.role))?;
 
         Ok(deserialized_answer)
     }
 
-    /// Receives a file and saves it in blocks (reads `file_size` bytes).
+    /// Reads one fragment of a stream of unknown length (from a whole message, including its header).
+    pub fn read_stream_fragment(
+        &mut self,
+        message: &[u8],
+    ) -> Result<StreamFragment, QuickTransferError> {
+        let (header, message) = self.read_message_header(message)?;
+
+        match header.as_str() {
+            MESSAGE_STREAM_DATA => Ok(StreamFragment::Data(message.to_vec())),
+            MESSAGE_STREAM_END => Ok(StreamFragment::End(self.read_answer(message)?)),
+            _ => Err(QuickTransferError::SentInvalidData(self.role)),
+        }
+    }
+
+    /// Receives one fragment of a stream of unknown length.
+    /// If wait == true, then timeout for the first byte is not set (the stream may be slow to produce).
+    pub async fn receive_stream_fragment(
+        &mut self,
+        wait: bool,
+    ) -> Result<StreamFragment, QuickTransferError> {
+        let message = self.receive_tcp(wait).await?;
+
+        self.read_stream_fragment(&message)
+    }
+
+    /// Receives one result of a search: a match or the end of the search.
+    /// Timeout for the first byte is not set (the server may search for long).
+    pub async fn receive_find_event(&mut self) -> Result<FindEvent, QuickTransferError> {
+        let message = self.receive_tcp(true).await?;
+        let (header, message) = self.read_message_header(&message)?;
+
+        match header.as_str() {
+            MESSAGE_FIND_MATCH => Ok(FindEvent::Match(self.read_answer(message)?)),
+            MESSAGE_FIND_END => Ok(FindEvent::End(self.read_answer(message)?)),
+            _ => Err(QuickTransferError::SentInvalidData(self.role)),
+        }
+    }
+
+    /// Receives a progress report of a copy or its result.
+    /// Timeout for the first byte is not set (copying may take long).
+    pub async fn receive_copy_event(&mut self) -> Result<CopyEvent, QuickTransferError> {
+        let message = self.receive_tcp(true).await?;
+        let (header, message) = self.read_message_header(&message)?;
+
+        match header.as_str() {
+            MESSAGE_COPY_PROGRESS => Ok(CopyEvent::Progress(self.read_answer(message)?)),
+            MESSAGE_COPY_ANSWER => Ok(CopyEvent::End(self.read_answer(message)?)),
+            _ => Err(QuickTransferError::SentInvalidData(self.role)),
+        }
+    }
+
+    /// Receives an update of a followed file or the end of following. The server may disconnect
+    /// while a file is followed. Timeout for the first byte is not set (a file may not change
+    /// for a long time).
+    pub async fn receive_follow_event(&mut self) -> Result<FollowEvent, QuickTransferError> {
+        let message = self.receive_tcp(true).await?;
+        let (header, message) = self.read_message_header(&message)?;
+
+        match header.as_str() {
+            MESSAGE_FOLLOW_UPDATE => Ok(FollowEvent::Update(self.read_answer(message)?)),
+            MESSAGE_FOLLOW_END => Ok(FollowEvent::End(self.read_answer(message)?)),
+            MESSAGE_DISCONNECT => Err(QuickTransferError::RemoteClosedConnection(self.role)),
+            _ => Err(QuickTransferError::SentInvalidData(self.role)),
+        }
+    }
+
+    /// Receives a file and saves it in blocks (reads `file_size` bytes). If `try_all` is set,
+    /// a write error does not stop receiving. Returns whether the whole file has been written.
     pub async fn receive_file(
         &mut self,
-        mut file: File,
+        mut file: impl Write,
         file_size: u64,
         file_path: &Path,
         try_all: bool,
-    ) -> Result<(), QuickTransferError> {
+    ) -> Result<bool, QuickTransferError> {
         let mut bytes_to_receive_left = file_size;
 
         let mut just_receive = false;
@@ -219,6 +401,6 @@ impl CommunicationAgent<'_> {
             bytes_to_receive_left -= received_bytes;
         }
 
-        Ok(())
+        Ok(!just_receive)
     }
 }
@@ -1,4 +1,7 @@
-use aes_gcm::{aead::Aead, Nonce};
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Nonce,
+};
 use byteorder::{WriteBytesExt, BE};
 use rand::{rngs::OsRng, RngCore};
 use serde::Serialize;
@@ -6,22 +9,75 @@ use std::{
     fs::File,
     io::{self, Read},
     path::Path,
+    sync::atomic::Ordering,
 };
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 
 use crate::common::{
-    directory_description, map_tcp_error,
+    archive::{spawn_tar_builder, TarCompression},
+    associated_data,
+    checksum::ChecksumAlgorithm,
+    copy::spawn_copy,
+    directory_description,
+    find::spawn_find,
+    map_tcp_error,
     messages::{
-        EncryptedMessage, MessageDirectoryContents, UnencryptedMessage, MAX_FILE_FRAGMENT_SIZE,
-        MESSAGE_CD, MESSAGE_DIR, MESSAGE_DISCONNECT, MESSAGE_DOWNLOAD, MESSAGE_DOWNLOAD_SUCCESS,
-        MESSAGE_LS, MESSAGE_MKDIR, MESSAGE_REMOVE, MESSAGE_RENAME, MESSAGE_UPLOAD,
+        CopyAnswer, EncryptedMessage, FindEnd, FindQuery, MessageDirectoryContents, ReadRange,
+        StreamEnd, UnencryptedMessage, MAX_FILE_FRAGMENT_SIZE, MESSAGE_CANCEL, MESSAGE_CD,
+        MESSAGE_CHECKSUM, MESSAGE_COPY, MESSAGE_COPY_ANSWER, MESSAGE_COPY_PROGRESS, MESSAGE_DF,
+        MESSAGE_DIR, MESSAGE_DISCONNECT, MESSAGE_DOWNLOAD, MESSAGE_DOWNLOAD_SUCCESS,
+        MESSAGE_DOWNLOAD_TAR, MESSAGE_DU, MESSAGE_FIND, MESSAGE_FIND_END, MESSAGE_FIND_MATCH,
+        MESSAGE_FOLLOW, MESSAGE_LOGIN, MESSAGE_LOGIN_FAIL, MESSAGE_LOGIN_REQUIRED, MESSAGE_LS,
+        MESSAGE_LS_LONG, MESSAGE_MKDIR, MESSAGE_READ, MESSAGE_REMOVE, MESSAGE_RENAME, MESSAGE_STAT,
+        MESSAGE_STREAM_DATA, MESSAGE_STREAM_END, MESSAGE_UPLOAD, MESSAGE_UPLOAD_ACCEPT,
+        MESSAGE_UPLOAD_CHECK, MESSAGE_UPLOAD_STREAM,
     },
-    CommunicationAgent, QuickTransferError, QuickTransferStream, QuickTransferStreamOption,
+    without_contents, CommunicationAgent, QuickTransferError, QuickTransferStream,
+    QuickTransferStreamOption, SymlinkPolicy,
 };
 
 impl QuickTransferStream {
     /// Sends a wrapped message over TCP.
     async fn send_tcp(&mut self, message: &[u8], flush: bool) -> Result<(), QuickTransferError> {
+        let mut message_to_send = vec![];
+
+        // The key is replaced right before a message, so that a receiver of a rekey message never
+        // waits for the next message:
+        if let QuickTransferStreamOption::Encrypted {
+            sending, limits, ..
+        } = &mut self.option
+        {
+            if sending.exceeds(limits) {
+                message_to_send = self.wrap_message(&[], true)?;
+                self.rekey_sending();
+            }
+        }
+        message_to_send.extend(self.wrap_message(message, false)?);
+
+        self.stream
+            .write_all(&message_to_send)
+            .await
+            .map_err(|error| map_tcp_error(error, self.role))?;
+        if flush {
+            self.stream
+                .flush()
+                .await
+                .map_err(|_| QuickTransferError::ErrorWhileSendingMessage(self.role))?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the key of sent messages.
+    fn rekey_sending(&mut self) {
+        if let QuickTransferStreamOption::Encrypted { sending, .. } = &mut self.option {
+            sending.rekey();
+        }
+    }
+
+    /// Wraps (and encrypts if needed) a message so that it can be sent. `rekey` marks a message
+    /// after which the key is replaced (only in an encrypted connection).
+    fn wrap_message(&mut self, message: &[u8], rekey: bool) -> Result<Vec<u8>, QuickTransferError> {
         let message_to_send = match &mut self.option {
             QuickTransferStreamOption::Unencrypted => {
                 let mut message_to_send: Vec<u8> = vec![];
@@ -40,16 +96,30 @@ impl QuickTransferStream {
 
                 message_to_send
             }
-            QuickTransferStreamOption::Encrypted { cipher, .. } => {
+            QuickTransferStreamOption::Encrypted { sending, .. } => {
                 let mut nonce = vec![0u8; 12];
                 OsRng.fill_bytes(&mut nonce);
                 let nonce_array = Nonce::from_slice(&nonce);
-                let cipher_text = cipher
-                    .encrypt(nonce_array, message)
+                // The number of the message and the rekey flag are authenticated as associated data:
+                let number = sending.messages;
+                let cipher_text = sending
+                    .cipher
+                    .encrypt(
+                        nonce_array,
+                        Payload {
+                            msg: message,
+                            aad: &associated_data(number, rekey),
+                        },
+                    )
                     .map_err(|_| QuickTransferError::Ciphering)?;
+                sending.messages += 1;
+                sending.messages_with_key += 1;
+                sending.bytes_with_key += message.len() as u64;
 
                 let mut message_to_send: Vec<u8> = vec![];
                 let message = bincode::serialize(&EncryptedMessage {
+                    number,
+                    rekey,
                     nonce,
                     content: cipher_text,
                 })
@@ -66,25 +136,19 @@ impl QuickTransferStream {
                 message_to_send
             }
         };
-        self.stream
-            .write_all(&message_to_send)
-            .await
-            .map_err(|error| map_tcp_error(error, self.role))?;
-        if flush {
-            self.stream
-                .flush()
-                .await
-                .map_err(|_| QuickTransferError::ErrorWhileSendingMessage(self.role))?;
-        }
 
-        Ok(())
+        Ok(message_to_send)
     }
 
     /// Sends message through bare (neither wrapped nor encrypted) TCP stream.
     pub async fn send_bare_message(&mut self, message: &str) -> Result<(), QuickTransferError> {
-        let message = message.as_bytes();
+        self.send_bare_data(message.as_bytes()).await
+    }
+
+    /// Sends bytes through bare (neither wrapped nor encrypted) TCP stream.
+    pub async fn send_bare_data(&mut self, data: &[u8]) -> Result<(), QuickTransferError> {
         self.stream
-            .write_all(message)
+            .write_all(data)
             .await
             .map_err(|error| map_tcp_error(error, self.role))?;
 
@@ -104,12 +168,24 @@ impl CommunicationAgent<'_> {
     }
 
     /// Sends directory description: header, description length, description.
+    /// Unless `list_contents` is set, only the location is sent.
     pub async fn send_directory_description(
         &mut self,
         directory_path: &Path,
         root_directory_path: &Path,
+        symlink_policy: SymlinkPolicy,
+        list_contents: bool,
     ) -> Result<(), QuickTransferError> {
-        let directory_contents = directory_description(directory_path, root_directory_path);
+        let directory_contents =
+            directory_description(directory_path, root_directory_path, symlink_policy).map(
+                |directory_contents| {
+                    if list_contents {
+                        directory_contents
+                    } else {
+                        without_contents(directory_contents)
+                    }
+                },
+            );
 
         let mut dir_message = MESSAGE_DIR.as_bytes().to_vec();
 
@@ -129,6 +205,43 @@ impl CommunicationAgent<'_> {
         Ok(())
     }
 
+    /// Sends a message asking the client to log in (header).
+    pub async fn send_login_required(&mut self) -> Result<(), QuickTransferError> {
+        self.send_tcp(MESSAGE_LOGIN_REQUIRED.as_bytes(), true)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Sends a log in message: header, user name length, user name, password length, password.
+    pub async fn send_login_request(
+        &mut self,
+        user_name: &str,
+        password: &str,
+    ) -> Result<(), QuickTransferError> {
+        let mut login_message = MESSAGE_LOGIN.as_bytes().to_vec();
+
+        // We assume that usize <= u64:
+        WriteBytesExt::write_u64::<BE>(&mut login_message, user_name.len().try_into().unwrap())
+            .map_err(|_| QuickTransferError::Fatal)?;
+        login_message.extend(user_name.as_bytes());
+
+        WriteBytesExt::write_u64::<BE>(&mut login_message, password.len().try_into().unwrap())
+            .map_err(|_| QuickTransferError::Fatal)?;
+        login_message.extend(password.as_bytes());
+
+        self.send_tcp(login_message.as_slice(), true).await?;
+
+        Ok(())
+    }
+
+    /// Sends a message rejecting a log in (header).
+    pub async fn send_login_fail(&mut self) -> Result<(), QuickTransferError> {
+        self.send_tcp(MESSAGE_LOGIN_FAIL.as_bytes(), true).await?;
+
+        Ok(())
+    }
+
     /// Sends change directory message: header, directory name length, directory length.
     pub async fn send_change_directory(
         &mut self,
@@ -154,6 +267,13 @@ impl CommunicationAgent<'_> {
         Ok(())
     }
 
+    /// Sends a `ls -l` message (header).
+    pub async fn send_list_directory_long(&mut self) -> Result<(), QuickTransferError> {
+        self.send_tcp(MESSAGE_LS_LONG.as_bytes(), true).await?;
+
+        Ok(())
+    }
+
     /// Sends file download request: header, file name length, file name.
     pub async fn send_download_request(
         &mut self,
@@ -172,6 +292,279 @@ impl CommunicationAgent<'_> {
         Ok(())
     }
 
+    /// Sends directory archive download request: header, directory name length, directory name, compression.
+    pub async fn send_download_tar_request(
+        &mut self,
+        directory_name: &str,
+        compression: TarCompression,
+    ) -> Result<(), QuickTransferError> {
+        let mut download_tar_message = MESSAGE_DOWNLOAD_TAR.as_bytes().to_vec();
+
+        // We assume that usize <= u64:
+        WriteBytesExt::write_u64::<BE>(
+            &mut download_tar_message,
+            directory_name.len().try_into().unwrap(),
+        )
+        .map_err(|_| QuickTransferError::Fatal)?;
+
+        download_tar_message.extend(directory_name.as_bytes());
+
+        WriteBytesExt::write_u64::<BE>(&mut download_tar_message, compression.to_u64())
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        self.send_tcp(download_tar_message.as_slice(), true).await?;
+
+        Ok(())
+    }
+
+    /// Sends one fragment of a stream of unknown length: header, data.
+    pub async fn send_stream_data(&mut self, data: &[u8]) -> Result<(), QuickTransferError> {
+        let mut stream_data_message = MESSAGE_STREAM_DATA.as_bytes().to_vec();
+        stream_data_message.extend(data);
+
+        self.send_tcp(stream_data_message.as_slice(), false).await?;
+
+        Ok(())
+    }
+
+    /// Sends the end of a stream of unknown length: header, answer length, answer.
+    pub async fn send_stream_end(
+        &mut self,
+        stream_end: &StreamEnd,
+    ) -> Result<(), QuickTransferError> {
+        self.send_answer(MESSAGE_STREAM_END, stream_end).await
+    }
+
+    /// Generates a tar archive of a directory on the fly and sends it as a stream.
+    pub async fn send_tar_stream(
+        &mut self,
+        directory_path: &Path,
+        archive_name: &str,
+        compression: TarCompression,
+    ) -> Result<(), QuickTransferError> {
+        let (mut fragments, builder) = spawn_tar_builder(
+            directory_path.to_path_buf(),
+            String::from(archive_name),
+            compression,
+        );
+
+        while let Some(fragment) = fragments.recv().await {
+            self.send_stream_data(&fragment).await?;
+        }
+
+        let stream_end = match builder.await {
+            Ok(Ok(())) => StreamEnd::Success,
+            _ => StreamEnd::Fail,
+        };
+
+        self.send_stream_end(&stream_end).await
+    }
+
+    /// Sends a search request: header, query length, query.
+    pub async fn send_find_request(&mut self, query: &FindQuery) -> Result<(), QuickTransferError> {
+        let mut find_message = MESSAGE_FIND.as_bytes().to_vec();
+
+        let query = bincode::serialize(query).map_err(|_| QuickTransferError::Fatal)?;
+
+        // We assume that usize <= u64:
+        WriteBytesExt::write_u64::<BE>(&mut find_message, query.len().try_into().unwrap())
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        find_message.extend(query);
+
+        self.send_tcp(find_message.as_slice(), true).await?;
+
+        Ok(())
+    }
+
+    /// Searches `directory_path`, sending every match as soon as it is found and finishing with
+    /// a "FIND_END". The client may cancel the search while it is running.
+    pub async fn send_find_results(
+        &mut self,
+        directory_path: &Path,
+        query: FindQuery,
+    ) -> Result<(), QuickTransferError> {
+        let (cancelled, mut matches, search) = spawn_find(directory_path.to_path_buf(), query);
+
+        loop {
+            tokio::select! {
+                found = matches.recv() => match found {
+                    Some(found) => self.send_answer(MESSAGE_FIND_MATCH, &found).await?,
+                    None => break,
+                },
+                message = self.wait_for_message() => {
+                    message?;
+                    let message = self.receive_tcp(false).await?;
+                    let (header, _) = self.read_message_header(&message)?;
+                    if header != MESSAGE_CANCEL {
+                        return Err(QuickTransferError::SentInvalidData(self.role));
+                    }
+
+                    // Matches found in the meantime are not sent anymore:
+                    cancelled.store(true, Ordering::Relaxed);
+                    drop(matches);
+                    break;
+                }
+            }
+        }
+
+        let find_end = search.await.unwrap_or(FindEnd::ErrorReading);
+
+        self.send_answer(MESSAGE_FIND_END, &find_end).await
+    }
+
+    /// Sends a checksum request: header, file name length, file name, algorithm.
+    pub async fn send_checksum_request(
+        &mut self,
+        file_name: &str,
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<(), QuickTransferError> {
+        let mut checksum_message = MESSAGE_CHECKSUM.as_bytes().to_vec();
+
+        // We assume that usize <= u64:
+        WriteBytesExt::write_u64::<BE>(&mut checksum_message, file_name.len().try_into().unwrap())
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        checksum_message.extend(file_name.as_bytes());
+
+        WriteBytesExt::write_u64::<BE>(&mut checksum_message, algorithm.to_u64())
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        self.send_tcp(checksum_message.as_slice(), true).await?;
+
+        Ok(())
+    }
+
+    /// Sends a copy request: header, source length, source, destination length, destination,
+    /// flags.
+    pub async fn send_copy_request(
+        &mut self,
+        source: &str,
+        destination: &str,
+        flags: u64,
+    ) -> Result<(), QuickTransferError> {
+        let mut copy_message = MESSAGE_COPY.as_bytes().to_vec();
+
+        // We assume that usize <= u64:
+        WriteBytesExt::write_u64::<BE>(&mut copy_message, source.len().try_into().unwrap())
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        copy_message.extend(source.as_bytes());
+
+        // We assume that usize <= u64:
+        WriteBytesExt::write_u64::<BE>(&mut copy_message, destination.len().try_into().unwrap())
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        copy_message.extend(destination.as_bytes());
+
+        WriteBytesExt::write_u64::<BE>(&mut copy_message, flags)
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        self.send_tcp(copy_message.as_slice(), true).await?;
+
+        Ok(())
+    }
+
+    /// Copies `source` to `destination`, sending progress reports of a long copy and finishing
+    /// with a "COPY_ANSWER".
+    pub async fn send_copy_results(
+        &mut self,
+        source: &Path,
+        destination: &Path,
+        overwrite: bool,
+    ) -> Result<(), QuickTransferError> {
+        let (mut progress, copy) =
+            spawn_copy(source.to_path_buf(), destination.to_path_buf(), overwrite);
+
+        while let Some(progress) = progress.recv().await {
+            self.send_answer(MESSAGE_COPY_PROGRESS, &progress).await?;
+        }
+
+        let copy_answer = match copy.await {
+            Ok(Ok(())) => CopyAnswer::Success,
+            Ok(Err(error)) if error.kind() == io::ErrorKind::AlreadyExists => {
+                CopyAnswer::DestinationExists
+            }
+            Ok(Err(error)) if error.kind() == io::ErrorKind::StorageFull => {
+                CopyAnswer::NotEnoughSpace
+            }
+            _ => CopyAnswer::ErrorCopying,
+        };
+
+        self.send_answer(MESSAGE_COPY_ANSWER, &copy_answer).await
+    }
+
+    /// Sends a message cancelling a running operation (header).
+    pub async fn send_cancel(&mut self) -> Result<(), QuickTransferError> {
+        self.send_tcp(MESSAGE_CANCEL.as_bytes(), true).await?;
+
+        Ok(())
+    }
+
+    /// Sends a follow request: header, file name length, file name, offset.
+    pub async fn send_follow_request(
+        &mut self,
+        file_name: &str,
+        offset: u64,
+    ) -> Result<(), QuickTransferError> {
+        let mut follow_message = MESSAGE_FOLLOW.as_bytes().to_vec();
+
+        // We assume that usize <= u64:
+        WriteBytesExt::write_u64::<BE>(&mut follow_message, file_name.len().try_into().unwrap())
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        follow_message.extend(file_name.as_bytes());
+
+        WriteBytesExt::write_u64::<BE>(&mut follow_message, offset)
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        self.send_tcp(follow_message.as_slice(), true).await?;
+
+        Ok(())
+    }
+
+    /// Sends a ranged read request: header, file name length, file name, range length, range.
+    pub async fn send_read_request(
+        &mut self,
+        file_name: &str,
+        range: &ReadRange,
+    ) -> Result<(), QuickTransferError> {
+        let mut read_message = MESSAGE_READ.as_bytes().to_vec();
+
+        // We assume that usize <= u64:
+        WriteBytesExt::write_u64::<BE>(&mut read_message, file_name.len().try_into().unwrap())
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        read_message.extend(file_name.as_bytes());
+
+        let range = bincode::serialize(range).map_err(|_| QuickTransferError::Fatal)?;
+
+        // We assume that usize <= u64:
+        WriteBytesExt::write_u64::<BE>(&mut read_message, range.len().try_into().unwrap())
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        read_message.extend(range);
+
+        self.send_tcp(read_message.as_slice(), true).await?;
+
+        Ok(())
+    }
+
+    /// Sends a stat request: header, file/directory name length, file/directory name.
+    pub async fn send_stat_request(&mut self, file_name: &str) -> Result<(), QuickTransferError> {
+        let mut stat_message = MESSAGE_STAT.as_bytes().to_vec();
+
+        // We assume that usize <= u64:
+        WriteBytesExt::write_u64::<BE>(&mut stat_message, file_name.len().try_into().unwrap())
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        stat_message.extend(file_name.as_bytes());
+
+        self.send_tcp(stat_message.as_slice(), true).await?;
+
+        Ok(())
+    }
+
     /// Sends download success message: header, file size (in bytes) (without file contents!)
     pub async fn send_download_success(
         &mut self,
@@ -224,13 +617,12 @@ impl CommunicationAgent<'_> {
         Ok(())
     }
 
-    /// Sends an upload message: header, file size (in bytes), file contents.
-    pub async fn send_upload(
+    /// Sends an upload request: header, file name length, file name, file size (in bytes).
+    /// File contents are sent (by `send_file`) after the server accepts the upload.
+    pub async fn send_upload_request(
         &mut self,
-        file: File,
-        file_size: u64,
         file_name: &str,
-        file_path: &Path,
+        file_size: u64,
     ) -> Result<(), QuickTransferError> {
         let mut upload_message = MESSAGE_UPLOAD.as_bytes().to_vec();
 
@@ -246,11 +638,107 @@ impl CommunicationAgent<'_> {
 
         self.send_tcp(upload_message.as_slice(), true).await?;
 
-        self.send_file(file, file_size, file_path).await?;
+        Ok(())
+    }
+
+    /// Sends an upload check request: header, file name length, file name, file size (in bytes),
+    /// checksum algorithm.
+    pub async fn send_upload_check_request(
+        &mut self,
+        file_name: &str,
+        file_size: u64,
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<(), QuickTransferError> {
+        let mut check_message = MESSAGE_UPLOAD_CHECK.as_bytes().to_vec();
+
+        // We assume that usize <= u64:
+        WriteBytesExt::write_u64::<BE>(&mut check_message, file_name.len().try_into().unwrap())
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        check_message.extend(file_name.as_bytes());
+
+        WriteBytesExt::write_u64::<BE>(&mut check_message, file_size)
+            .map_err(|_| QuickTransferError::Fatal)?;
+        WriteBytesExt::write_u64::<BE>(&mut check_message, algorithm.to_u64())
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        self.send_tcp(check_message.as_slice(), true).await?;
+
+        Ok(())
+    }
+
+    /// Sends an upload accept message (header).
+    pub async fn send_upload_accept(&mut self) -> Result<(), QuickTransferError> {
+        self.send_tcp(MESSAGE_UPLOAD_ACCEPT.as_bytes(), true)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Sends a disk usage request: header, path length, path, whether to summarize (0 or 1).
+    pub async fn send_du_request(
+        &mut self,
+        path: &str,
+        summarize: bool,
+    ) -> Result<(), QuickTransferError> {
+        let mut du_message = MESSAGE_DU.as_bytes().to_vec();
+
+        // We assume that usize <= u64:
+        WriteBytesExt::write_u64::<BE>(&mut du_message, path.len().try_into().unwrap())
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        du_message.extend(path.as_bytes());
+
+        WriteBytesExt::write_u64::<BE>(&mut du_message, summarize.into())
+            .map_err(|_| QuickTransferError::Fatal)?;
+
+        self.send_tcp(du_message.as_slice(), true).await?;
 
         Ok(())
     }
 
+    /// Sends a free space request (header).
+    pub async fn send_df_request(&mut self) -> Result<(), QuickTransferError> {
+        self.send_tcp(MESSAGE_DF.as_bytes(), true).await?;
+
+        Ok(())
+    }
+
+    /// Sends an upload of unknown length: header, file name length, file name, followed by a stream
+    /// of contents read from `reader` until EOF. Returns the end of the stream that has been sent.
+    pub async fn send_upload_stream(
+        &mut self,
+        mut reader: impl AsyncRead + Unpin,
+        file_name: &str,
+    ) -> Result<StreamEnd, QuickTransferError> {
+        let mut upload_stream_message = MESSAGE_UPLOAD_STREAM.as_bytes().to_vec();
+
+        // We assume that usize <= u64:
+        WriteBytesExt::write_u64::<BE>(
+            &mut upload_stream_message,
+            file_name.len().try_into().unwrap(),
+        )
+        .map_err(|_| QuickTransferError::Fatal)?;
+
+        upload_stream_message.extend(file_name.as_bytes());
+
+        self.send_tcp(upload_stream_message.as_slice(), true)
+            .await?;
+
+        let mut buffer = [0_u8; MAX_FILE_FRAGMENT_SIZE];
+        let stream_end = loop {
+            match reader.read(&mut buffer).await {
+                Ok(0) => break StreamEnd::Success,
+                Ok(read_bytes) => self.send_stream_data(&buffer[..read_bytes]).await?,
+                Err(_) => break StreamEnd::Fail,
+            }
+        };
+
+        self.send_stream_end(&stream_end).await?;
+
+        Ok(stream_end)
+    }
+
     /// Sends an mkdir message: header, name length, name.
     pub async fn send_mkdir(&mut self, directory_name: &str) -> Result<(), QuickTransferError> {
         let mut mkdir_message = MESSAGE_MKDIR.as_bytes().to_vec();
@@ -274,6 +762,7 @@ impl CommunicationAgent<'_> {
         &mut self,
         file_name: &str,
         new_name: &str,
+        flags: u64,
     ) -> Result<(), QuickTransferError> {
         let mut rename_message = MESSAGE_RENAME.as_bytes().to_vec();
 
@@ -289,13 +778,20 @@ impl CommunicationAgent<'_> {
 
         rename_message.extend(new_name.as_bytes());
 
+        WriteBytesExt::write_u64::<BE>(&mut rename_message, flags)
+            .map_err(|_| QuickTransferError::Fatal)?;
+
         self.send_tcp(rename_message.as_slice(), true).await?;
 
         Ok(())
     }
 
-    /// Sends remove request: header, file name length, file name.
-    pub async fn send_remove_request(&mut self, file_name: &str) -> Result<(), QuickTransferError> {
+    /// Sends remove request: header, file name length, file name, flags.
+    pub async fn send_remove_request(
+        &mut self,
+        file_name: &str,
+        flags: u64,
+    ) -> Result<(), QuickTransferError> {
         let mut remove_message = MESSAGE_REMOVE.as_bytes().to_vec();
 
         // We assume that usize <= u64:
@@ -304,6 +800,9 @@ impl CommunicationAgent<'_> {
 
         remove_message.extend(file_name.as_bytes());
 
+        WriteBytesExt::write_u64::<BE>(&mut remove_message, flags)
+            .map_err(|_| QuickTransferError::Fatal)?;
+
         self.send_tcp(remove_message.as_slice(), true).await?;
 
         Ok(())
@@ -344,7 +843,7 @@ mod test {
         net::{TcpListener, TcpStream},
     };
 
-    use crate::common::{ProgramRole, QuickTransferStream, QuickTransferStreamOption};
+    use crate::common::{ProgramRole, QuickTransferStream};
 
     #[tokio::test]
     async fn test_send_tcp() {
@@ -359,12 +858,8 @@ mod test {
         });
 
         let stream = TcpStream::connect(addr).await.unwrap();
-        let mut quick_transfer_stream = QuickTransferStream {
-            stream,
-            option: QuickTransferStreamOption::Unencrypted,
-            role: ProgramRole::Client,
-            timeout: 1,
-        };
+        let mut quick_transfer_stream =
+            QuickTransferStream::new_unencrypted(stream, ProgramRole::Client, 1);
 
         let message = b"Test message";
         let result = quick_transfer_stream.send_tcp(message, true).await;
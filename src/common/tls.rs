@@ -0,0 +1,250 @@
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms},
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime},
+    CertificateError, ClientConfig, DigitallySignedStruct, Error, ServerConfig, SignatureScheme,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::net::TcpStream;
+use tokio_rustls::{client::TlsStream, TlsAcceptor, TlsConnector};
+
+use crate::common::checksum::to_hex;
+
+/// Files (in the TLS directory) holding the server's certificate and its private key.
+const CERTIFICATE_FILE: &str = "certificate.der";
+const PRIVATE_KEY_FILE: &str = "private_key.der";
+
+/// File (in the TLS directory) holding fingerprints of servers trusted by the client.
+pub const KNOWN_SERVERS_FILE: &str = "known_servers";
+
+/// SHA-256 digest of a certificate (DER).
+pub type Fingerprint = [u8; 32];
+
+pub fn fingerprint(certificate: &[u8]) -> Fingerprint {
+    Sha256::digest(certificate).into()
+}
+
+/// Parses a fingerprint written in hex (bytes may be separated with colons).
+pub fn parse_fingerprint(text: &str) -> Option<Fingerprint> {
+    let digits: String = text.chars().filter(|&c| c != ':').collect();
+    if digits.len() != 64 || !digits.is_ascii() {
+        return None;
+    }
+
+    let mut fingerprint = [0_u8; 32];
+    for (i, byte) in fingerprint.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).ok()?;
+    }
+
+    Some(fingerprint)
+}
+
+/// Default directory of the certificate and known servers: `~/.quicktransfer`.
+/// It is deliberately not inside the shared root directory.
+pub fn default_tls_directory() -> PathBuf {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(".quicktransfer")
+}
+
+/// Loads the server's certificate and private key, generating a self-signed certificate
+/// on first start.
+pub fn load_or_generate_certificate(
+    directory: &Path,
+) -> io::Result<(CertificateDer<'static>, PrivateKeyDer<'static>)> {
+    let certificate_path = directory.join(CERTIFICATE_FILE);
+    let private_key_path = directory.join(PRIVATE_KEY_FILE);
+
+    if !certificate_path.exists() || !private_key_path.exists() {
+        let generated = rcgen::generate_simple_self_signed(vec![String::from("quicktransfer")])
+            .map_err(io::Error::other)?;
+
+        fs::create_dir_all(directory)?;
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(&private_key_path)?
+            .write_all(&generated.signing_key.serialize_der())?;
+        fs::write(&certificate_path, generated.cert.der())?;
+    }
+
+    let certificate = CertificateDer::from(fs::read(certificate_path)?);
+    let private_key = PrivatePkcs8KeyDer::from(fs::read(private_key_path)?);
+
+    Ok((certificate, private_key.into()))
+}
+
+pub fn tls_acceptor(
+    certificate: CertificateDer<'static>,
+    private_key: PrivateKeyDer<'static>,
+) -> Result<TlsAcceptor, Error> {
+    let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(vec![certificate], private_key)?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Accepts only the certificate with the pinned fingerprint instead of checking a chain of
+/// certificate authorities. If nothing is pinned yet, any certificate is accepted (and the
+/// client remembers its fingerprint).
+#[derive(Debug)]
+struct PinnedCertificateVerifier {
+    pinned: Option<Fingerprint>,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for PinnedCertificateVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        match self.pinned {
+            Some(pinned) if pinned != fingerprint(end_entity) => Err(Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            )),
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        certificate: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls12_signature(message, certificate, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        certificate: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls13_signature(message, certificate, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// Starts TLS on a connection to the server. Returns the TLS stream and the fingerprint of
+/// the server's certificate.
+pub async fn connect(
+    stream: TcpStream,
+    server_address: &str,
+    pinned: Option<Fingerprint>,
+) -> io::Result<(TlsStream<TcpStream>, Fingerprint)> {
+    let provider = Arc::new(ring::default_provider());
+    let verifier = PinnedCertificateVerifier {
+        pinned,
+        algorithms: provider.signature_verification_algorithms,
+    };
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+
+    // The name is not checked (the certificate is pinned), but it has to be valid:
+    let server_name = ServerName::try_from(server_address.to_string())
+        .unwrap_or_else(|_| ServerName::try_from("quicktransfer").unwrap());
+    let stream = TlsConnector::from(Arc::new(config))
+        .connect(server_name, stream)
+        .await?;
+
+    let certificate_fingerprint = stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(|certificates| certificates.first())
+        .map(|certificate| fingerprint(certificate))
+        .ok_or(io::Error::from(io::ErrorKind::InvalidData))?;
+
+    Ok((stream, certificate_fingerprint))
+}
+
+/// Checks whether a TLS error means that the server's certificate is not the pinned one.
+pub fn is_certificate_mismatch(error: &io::Error) -> bool {
+    matches!(
+        error
+            .get_ref()
+            .and_then(|error| error.downcast_ref::<Error>()),
+        Some(Error::InvalidCertificate(_))
+    )
+}
+
+/// Looks up a fingerprint remembered for `server` (`address:port`).
+pub fn known_fingerprint(directory: &Path, server: &str) -> Option<Fingerprint> {
+    let known_servers = fs::read_to_string(directory.join(KNOWN_SERVERS_FILE)).ok()?;
+
+    known_servers.lines().find_map(|line| {
+        let (known_server, known_fingerprint) = line.split_once(' ')?;
+        (known_server == server)
+            .then(|| parse_fingerprint(known_fingerprint.trim()))
+            .flatten()
+    })
+}
+
+/// Remembers the fingerprint of `server` (trust on first use).
+pub fn remember_fingerprint(
+    directory: &Path,
+    server: &str,
+    fingerprint: &Fingerprint,
+) -> io::Result<()> {
+    fs::create_dir_all(directory)?;
+    let mut known_servers = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(directory.join(KNOWN_SERVERS_FILE))?;
+
+    writeln!(known_servers, "{} {}", server, to_hex(fingerprint))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_known_fingerprints() {
+        let directory = env::temp_dir().join("quick_transfer_test_known_fingerprints");
+        let _ = fs::remove_dir_all(&directory);
+
+        assert_eq!(known_fingerprint(&directory, "[::1]:47842"), None);
+        remember_fingerprint(&directory, "[::1]:47842", &[0xab; 32]).unwrap();
+        remember_fingerprint(&directory, "10.0.0.1:47842", &[0x01; 32]).unwrap();
+
+        assert_eq!(
+            known_fingerprint(&directory, "[::1]:47842"),
+            Some([0xab; 32])
+        );
+        assert_eq!(
+            known_fingerprint(&directory, "10.0.0.1:47842"),
+            Some([0x01; 32])
+        );
+        assert_eq!(
+            parse_fingerprint(&format!("{}AB", "AB:".repeat(31))),
+            Some([0xab; 32])
+        );
+        assert_eq!(parse_fingerprint("abc"), None);
+    }
+}
@@ -0,0 +1,184 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use rand::rngs::OsRng;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use tokio::task;
+
+use crate::common::permissions::Permissions;
+
+/// Environment variable from which the password of a user is taken (if set).
+pub const PASSWORD_VARIABLE: &str = "QUICKTRANSFER_PASSWORD";
+
+/// Hash (with the default parameters) of a password nobody knows. Logins of unknown users are
+/// verified against it, so that they take as long as logins of existing users.
+pub const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$1CHfKmFpTo3nNpFu+Ninmw$cDiBoMuCdJ/W1cSV7atVhRAQNvynR89vzx7jJTHXG8o";
+
+/// An account in the server's user database.
+pub struct UserAccount {
+    /// Argon2id hash of the password (PHC string format).
+    pub password_hash: String,
+    /// The directory the user is confined to (canonical).
+    pub root_directory: PathBuf,
+    /// Permissions of the user (if not given, the server's permissions apply).
+    pub permissions: Option<Permissions>,
+}
+
+/// User accounts by user names.
+pub type UserDatabase = HashMap<String, UserAccount>;
+
+/// Loads the user database. Every line of the file has the form
+/// `user_name:password_hash:permissions:root_directory` (permissions may be empty); lines of
+/// the older form `user_name:password_hash:root_directory` (recognized by the lack of valid
+/// permissions before a colon) get the server's permissions. Empty lines and lines starting
+/// with `#` are skipped. Relative root directories are relative to the
+/// directory holding the file.
+pub fn load_users(path: &Path) -> Result<UserDatabase, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|_| format!("The users file `{}` cannot be read.", path.display()))?;
+    let base_directory = path.parent().unwrap_or(Path::new("."));
+    let mut users = UserDatabase::new();
+
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid_line =
+            |reason: &str| format!("Line {} of the users file {}.", line_number + 1, reason);
+
+        // Password hashes never contain colons, but root directories can:
+        let fields: Vec<&str> = line.splitn(3, ':').collect();
+        let [user_name, password_hash, rest] = fields[..] else {
+            return Err(invalid_line(
+                "should have the form `user_name:password_hash:permissions:root_directory`",
+            ));
+        };
+        let (permissions, root_directory) = match rest.split_once(':') {
+            Some((permissions, root_directory))
+                if permissions.is_empty() || Permissions::from_spec(permissions).is_some() =>
+            {
+                (permissions, root_directory)
+            }
+            _ => ("", rest),
+        };
+        if user_name.is_empty() {
+            return Err(invalid_line("has an empty user name"));
+        }
+        if PasswordHash::new(password_hash).is_err() {
+            return Err(invalid_line(
+                "has an invalid password hash (use `--hash-password` to create one)",
+            ));
+        }
+        let permissions = Permissions::from_spec(permissions);
+        let Ok(root_directory) = base_directory.join(root_directory).canonicalize() else {
+            if permissions.is_none() && root_directory.contains(':') {
+                return Err(invalid_line(
+                    "has unknown permissions or a root directory that does not exist",
+                ));
+            }
+            return Err(invalid_line("has a root directory that does not exist"));
+        };
+        if !root_directory.is_dir() {
+            return Err(invalid_line("has a root directory that is not a directory"));
+        }
+
+        let account = UserAccount {
+            password_hash: password_hash.to_string(),
+            root_directory,
+            permissions,
+        };
+        if users.insert(user_name.to_string(), account).is_some() {
+            return Err(invalid_line("repeats a user name"));
+        }
+    }
+
+    Ok(users)
+}
+
+/// Hashes a password with Argon2id (default parameters) and a random salt.
+pub fn hash_password(password: &str) -> Option<String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .ok()
+        .map(|hash| hash.to_string())
+}
+
+pub fn verify_password(password_hash: &str, password: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+/// Verifies the password in a separate thread (the verification is deliberately slow).
+pub async fn spawn_verify_password(password_hash: String, password: String) -> bool {
+    task::spawn_blocking(move || verify_password(&password_hash, &password))
+        .await
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_load_users() {
+        let directory = env::temp_dir().join("quick_transfer_test_load_users");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("alice")).unwrap();
+        fs::create_dir_all(directory.join("a:b")).unwrap();
+
+        let password_hash = hash_password("secret").unwrap();
+        let users_file = directory.join("users");
+        fs::write(
+            &users_file,
+            format!(
+                "# Users:\n\nalice:{}::alice\ncarol:{}:read-only:alice\ndave:{}:alice\neve:{}:a:b\nfrank:{}:list:a:b\n",
+                password_hash, password_hash, password_hash, password_hash, password_hash
+            ),
+        )
+        .unwrap();
+
+        let users = load_users(&users_file).unwrap();
+        let alice = &users["alice"];
+        assert_eq!(
+            alice.root_directory,
+            directory.join("alice").canonicalize().unwrap()
+        );
+        assert!(verify_password(&alice.password_hash, "secret"));
+        assert!(!verify_password(&alice.password_hash, "wrong"));
+        assert!(!verify_password(DUMMY_PASSWORD_HASH, "secret"));
+        assert_eq!(
+            PasswordHash::new(DUMMY_PASSWORD_HASH).unwrap().params,
+            PasswordHash::new(&password_hash).unwrap().params
+        );
+        assert_eq!(alice.permissions, None);
+        assert_eq!(users["carol"].permissions, Some(Permissions::READ_ONLY));
+        assert_eq!(users["dave"].root_directory, alice.root_directory);
+        assert_eq!(users["dave"].permissions, None);
+        // Old lines with colons in root directories:
+        let colon_directory = directory.join("a:b").canonicalize().unwrap();
+        assert_eq!(users["eve"].root_directory, colon_directory);
+        assert_eq!(users["eve"].permissions, None);
+        assert_eq!(users["frank"].root_directory, colon_directory);
+        assert_eq!(users["frank"].permissions, Permissions::from_spec("list"));
+
+        fs::write(&users_file, format!("bob:{}::missing\n", password_hash)).unwrap();
+        assert!(load_users(&users_file).is_err());
+        fs::write(&users_file, "bob:plain::alice\n").unwrap();
+        assert!(load_users(&users_file).is_err());
+    }
+}
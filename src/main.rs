@@ -1,13 +1,29 @@
-use argparse::{ArgumentParser, Store, StoreTrue};
+use argparse::{ArgumentParser, Collect, List, Store, StoreTrue};
 use colored::*;
-use std::{fs::File, io::Read, path::Path};
+use std::{
+    env,
+    fs::File,
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
 
 mod client;
 mod common;
 mod server;
 
 use crate::common::{
-    ProgramOptions, ProgramRole, QuickTransferError, DEFAULT_PORT, DEFAULT_TIMEOUT,
+    address_filter::{parse_network, AddressFilter},
+    key_exchange::{RekeyLimits, DEFAULT_REKEY_BYTES, DEFAULT_REKEY_SECONDS},
+    passphrase::PASSPHRASE_VARIABLE,
+    permissions::Permissions,
+    rate_limit::{
+        RateLimits, DEFAULT_CONNECTIONS_PER_MINUTE, DEFAULT_LOCKOUT_SECONDS, DEFAULT_MAX_FAILURES,
+    },
+    tls::{default_tls_directory, parse_fingerprint},
+    users::{hash_password, load_users, PASSWORD_VARIABLE},
+    ProgramOptions, ProgramRole, QuickTransferError, SymlinkPolicy, DEFAULT_PORT, DEFAULT_TIMEOUT,
 };
 
 /// Parses program arguments.
@@ -18,6 +34,26 @@ fn parse_arguments() -> Option<ProgramOptions> {
     let mut root_directory = String::new();
     let mut timeout: u16 = DEFAULT_TIMEOUT;
     let mut aes_key_file = String::new();
+    let mut use_passphrase = false;
+    let mut require_encryption = false;
+    let mut rekey_bytes: u64 = DEFAULT_REKEY_BYTES;
+    let mut rekey_seconds: u64 = DEFAULT_REKEY_SECONDS;
+    let mut command: Vec<String> = vec![];
+    let mut allow_recursive_delete = false;
+    let mut symlink_policy = String::from("follow-within-root");
+    let mut tls = false;
+    let mut tls_directory = String::new();
+    let mut tls_fingerprint = String::new();
+    let mut users_file = String::new();
+    let mut user_name = String::new();
+    let mut print_password_hash = false;
+    let mut permissions = String::new();
+    let mut read_only = false;
+    let mut allowed_networks: Vec<String> = vec![];
+    let mut denied_networks: Vec<String> = vec![];
+    let mut connections_per_minute: u32 = DEFAULT_CONNECTIONS_PER_MINUTE;
+    let mut max_failures: u32 = DEFAULT_MAX_FAILURES;
+    let mut lockout_seconds: u64 = DEFAULT_LOCKOUT_SECONDS;
 
     let parsing_result: Result<(), i32>;
 
@@ -41,15 +77,58 @@ fn parse_arguments() -> Option<ProgramOptions> {
             "Specify timeout (in seconds) for waiting for the whole message. Default: `5`",
         );
         argument_parser.refer(&mut aes_key_file).add_option(&["-k", "--key"], Store, "Path to 32-byte file holding an AES key (pure bytes). If not specified, the connection won't be encrypted.");
+        argument_parser.refer(&mut use_passphrase).add_option(&["--passphrase"], StoreTrue, "Encrypt the connection with an AES key derived from a passphrase (taken from `QUICKTRANSFER_PASSPHRASE` environment variable or prompted for). A server may be given both a key and a passphrase.");
+        argument_parser.refer(&mut require_encryption).add_option(&["--require-encryption"], StoreTrue, "In server mode: reject clients connecting without encryption (requires a key or a passphrase)");
+        argument_parser.refer(&mut rekey_bytes).add_option(&["--rekey-bytes"], Store, "Number of bytes sent in an encrypted connection after which the key is replaced. Default: `1073741824` (1 GiB)");
+        argument_parser.refer(&mut rekey_seconds).add_option(&["--rekey-seconds"], Store, "Time (in seconds) after which the key of an encrypted connection is replaced. Default: `3600`");
+        argument_parser.refer(&mut tls).add_option(&["--tls"], StoreTrue, "Run the connection over TLS. The server generates a self-signed certificate on first start; the client pins its fingerprint (remembered on first connection). Both sides have to use this option.");
+        argument_parser.refer(&mut tls_directory).add_option(&["--tls-dir"], Store, "Directory holding the server's certificate and the client's known servers. Default: `~/.quicktransfer`");
+        argument_parser.refer(&mut tls_fingerprint).add_option(&["--fingerprint"], Store, "In client mode: SHA-256 fingerprint (hex) of the server's certificate to accept, instead of the remembered one");
+        argument_parser.refer(&mut users_file).add_option(&["--users"], Store, "In server mode: path to a file with user accounts (lines `user_name:password_hash:permissions:root_directory`, permissions may be empty). Clients have to log in and each user is confined to their root directory. Requires `--tls` or `--require-encryption`.");
+        argument_parser.refer(&mut user_name).add_option(&["-u", "--user"], Store, "In client mode: log in as this user (the password is taken from `QUICKTRANSFER_PASSWORD` environment variable or prompted for)");
+        argument_parser.refer(&mut print_password_hash).add_option(&["--hash-password"], StoreTrue, "Print a password hash for the users file (the password is taken from `QUICKTRANSFER_PASSWORD` environment variable or prompted for) and exit");
+        argument_parser.refer(&mut permissions).add_option(&["--permissions"], Store, "In server mode: what clients may do: `full`, `read-only`, `upload-only` or a comma-separated list of `list`, `download`, `upload`, `overwrite`, `mkdir`, `rename` and `delete`. Users with their own permissions in the users file are not affected. Default: `full`");
+        argument_parser.refer(&mut read_only).add_option(&["--read-only"], StoreTrue, "In server mode: clients may only list directories and download files (the same as `--permissions read-only`)");
+        argument_parser.refer(&mut allowed_networks).add_option(&["--allow"], Collect, "In server mode: accept connections only from this range of addresses (CIDR, e.g. `192.168.0.0/16` or `fd00::/8`, or a single address). Can be given multiple times.");
+        argument_parser.refer(&mut denied_networks).add_option(&["--deny"], Collect, "In server mode: reject connections from this range of addresses (CIDR or a single address), even if it is allowed. Can be given multiple times.");
+        argument_parser.refer(&mut connections_per_minute).add_option(&["--max-connections-per-minute"], Store, "In server mode: number of connections accepted from one address per minute; further ones are closed before the handshake (`0` -- no limit). Default: `120`");
+        argument_parser.refer(&mut max_failures).add_option(&["--max-auth-failures"], Store, "In server mode: number of failed authentications (wrong key, passphrase or password) after which an address is locked out (`0` -- never). Default: `5`");
+        argument_parser.refer(&mut lockout_seconds).add_option(&["--lockout-seconds"], Store, "In server mode: time (in seconds) for which a locked out address cannot connect. Default: `300`");
+        argument_parser
+            .refer(&mut allow_recursive_delete)
+            .add_option(
+                &["--allow-recursive-delete"],
+                StoreTrue,
+                "In server mode: allow clients to remove non-empty directories (`rm -r`)",
+            );
+        argument_parser.refer(&mut symlink_policy).add_option(&["--symlinks"], Store, "In server mode: how symbolic links are treated: `deny` (cannot be followed nor operated on), `follow-within-root` (followed if they point inside the root), `follow-all` (always followed) or `show-as-link` (never followed, but can be operated on). Default: `follow-within-root`");
+        argument_parser.refer(&mut command).add_argument("command", List, "In client mode: a command to execute instead of starting the interactive mode (e.g. `upload - backup.tar`). `-` as a local path means stdin/stdout. Commands with options should be preceded by `--`.");
+        argument_parser.silence_double_dash(true);
 
         parsing_result = argument_parser.parse_args();
     }
 
+    if print_password_hash && parsing_result.is_ok() {
+        match read_secret("Password: ", PASSWORD_VARIABLE)
+            .filter(|password| !password.is_empty())
+            .and_then(|password| hash_password(&password))
+        {
+            Some(password_hash) => println!("{}", password_hash),
+            None => eprintln!("A problem with reading or hashing the password has occurred."),
+        }
+        return None;
+    }
+
     if !role_server && server_ip_address.is_empty() {
         eprintln!("The server's address must be given in client mode.");
         return None;
     }
 
+    if role_server && !command.is_empty() {
+        eprintln!("Commands can be given only in client mode.");
+        return None;
+    }
+
     if !root_directory.is_empty() {
         if !Path::new(&root_directory).exists() {
             eprintln!("The root directory should be a valid directory.");
@@ -63,6 +142,11 @@ fn parse_arguments() -> Option<ProgramOptions> {
         server_ip_address = String::from("::");
     }
 
+    let Some(symlink_policy) = SymlinkPolicy::from_name(&symlink_policy) else {
+        eprintln!("Unknown symbolic link policy `{}`.", symlink_policy);
+        return None;
+    };
+
     let mut aes_key: Option<[u8; 32]> = None;
 
     if !aes_key_file.is_empty() {
@@ -78,6 +162,145 @@ fn parse_arguments() -> Option<ProgramOptions> {
         aes_key = Some(key);
     }
 
+    if require_encryption && (!role_server || (aes_key.is_none() && !use_passphrase)) {
+        eprintln!("Encryption can be required only by a server run with a key or a passphrase.");
+        return None;
+    }
+
+    if rekey_bytes == 0 || rekey_seconds == 0 {
+        eprintln!("Rekeying limits should be positive.");
+        return None;
+    }
+
+    if use_passphrase && aes_key.is_some() && !role_server {
+        eprintln!("A client can use either a key or a passphrase, not both.");
+        return None;
+    }
+
+    if (!tls_directory.is_empty() || !tls_fingerprint.is_empty()) && !tls {
+        eprintln!("TLS options can be given only together with `--tls`.");
+        return None;
+    }
+
+    let mut pinned_fingerprint = None;
+
+    if !tls_fingerprint.is_empty() {
+        if role_server {
+            eprintln!("A fingerprint can be given only in client mode.");
+            return None;
+        }
+        let Some(fingerprint) = parse_fingerprint(&tls_fingerprint) else {
+            eprintln!("The fingerprint should be 32 bytes written in hex.");
+            return None;
+        };
+        pinned_fingerprint = Some(fingerprint);
+    }
+
+    let tls_directory = if tls_directory.is_empty() {
+        default_tls_directory()
+    } else {
+        PathBuf::from(tls_directory)
+    };
+
+    if !users_file.is_empty() && (!role_server || (!tls && !require_encryption)) {
+        eprintln!("User accounts can be used only by a server run with `--tls` or `--require-encryption`.");
+        return None;
+    }
+
+    if !user_name.is_empty() && role_server {
+        eprintln!("A user name can be given only in client mode.");
+        return None;
+    }
+
+    if (read_only || !permissions.is_empty()) && !role_server {
+        eprintln!("Permissions can be given only in server mode.");
+        return None;
+    }
+
+    if read_only && !permissions.is_empty() {
+        eprintln!("`--read-only` and `--permissions` cannot be given together.");
+        return None;
+    }
+
+    let permissions = if read_only {
+        Permissions::READ_ONLY
+    } else if permissions.is_empty() {
+        Permissions::FULL
+    } else {
+        let Some(permissions) = Permissions::from_spec(&permissions) else {
+            eprintln!("Unknown permissions `{}`.", permissions);
+            return None;
+        };
+        permissions
+    };
+
+    if (!allowed_networks.is_empty() || !denied_networks.is_empty()) && !role_server {
+        eprintln!("Address ranges can be given only in server mode.");
+        return None;
+    }
+
+    if lockout_seconds == 0 {
+        eprintln!("The lockout time should be positive.");
+        return None;
+    }
+
+    let mut address_filter = AddressFilter::default();
+
+    for (networks, filtered) in [
+        (&allowed_networks, &mut address_filter.allowed),
+        (&denied_networks, &mut address_filter.denied),
+    ] {
+        for network in networks {
+            let Some(parsed_network) = parse_network(network) else {
+                eprintln!("`{}` is not a valid address range.", network);
+                return None;
+            };
+            filtered.push(parsed_network);
+        }
+    }
+
+    let mut users = None;
+
+    if !users_file.is_empty() {
+        match load_users(Path::new(&users_file)) {
+            Ok(loaded_users) => users = Some(loaded_users),
+            Err(error) => {
+                eprintln!("{}", error);
+                return None;
+            }
+        }
+    }
+
+    let mut passphrase: Option<String> = None;
+
+    if use_passphrase && parsing_result.is_ok() {
+        let Some(read_passphrase) = read_secret("Passphrase: ", PASSPHRASE_VARIABLE) else {
+            eprintln!(
+                "A problem with reading the passphrase has occurred. If standard input is not a terminal, set `{}` environment variable.",
+                PASSPHRASE_VARIABLE
+            );
+            return None;
+        };
+        if read_passphrase.is_empty() {
+            eprintln!("The passphrase cannot be empty.");
+            return None;
+        }
+        passphrase = Some(read_passphrase);
+    }
+
+    let mut password: Option<String> = None;
+
+    if !user_name.is_empty() && parsing_result.is_ok() {
+        let Some(read_password) = read_secret("Password: ", PASSWORD_VARIABLE) else {
+            eprintln!(
+                "A problem with reading the password has occurred. If standard input is not a terminal, set `{}` environment variable.",
+                PASSWORD_VARIABLE
+            );
+            return None;
+        };
+        password = Some(read_password);
+    }
+
     if parsing_result.is_ok() {
         Some(ProgramOptions {
             program_role: if role_server {
@@ -90,37 +313,111 @@ fn parse_arguments() -> Option<ProgramOptions> {
             root_directory,
             timeout,
             aes_key,
+            passphrase,
+            require_encryption,
+            command,
+            allow_recursive_delete,
+            symlink_policy,
+            rekey_limits: RekeyLimits::new(rekey_bytes, rekey_seconds),
+            tls,
+            tls_directory,
+            tls_fingerprint: pinned_fingerprint,
+            users,
+            user_name: (!user_name.is_empty()).then_some(user_name),
+            password,
+            permissions,
+            address_filter,
+            rate_limits: RateLimits {
+                connections_per_minute,
+                max_failures,
+                lockout: Duration::from_secs(lockout_seconds),
+            },
         })
     } else {
         None
     }
 }
 
+/// Reads a passphrase or a password from the environment variable or (if it is not set) from
+/// the terminal.
+fn read_secret(prompt: &str, variable: &str) -> Option<String> {
+    if let Ok(secret) = env::var(variable) {
+        return Some(secret);
+    }
+    // Standard input may carry data to upload, so the secret is read only from a terminal:
+    if !io::stdin().is_terminal() {
+        return None;
+    }
+
+    eprint!("{}", prompt);
+    io::stderr().flush().ok()?;
+
+    let echo_disabled = set_stdin_echo(false);
+    let mut line = String::new();
+    let result = io::stdin().read_line(&mut line);
+    if echo_disabled {
+        set_stdin_echo(true);
+        eprintln!();
+    }
+    result.ok()?;
+
+    Some(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Turns echoing typed characters on the terminal on or off. Returns whether it succeeded.
+#[cfg(unix)]
+fn set_stdin_echo(enabled: bool) -> bool {
+    // SAFETY: `termios` is plain old data, for which all-zero bytes are a valid value.
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+
+    // SAFETY: `termios` is a valid pointer for the duration of the calls.
+    unsafe {
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+            return false;
+        }
+        if enabled {
+            termios.c_lflag |= libc::ECHO;
+        } else {
+            termios.c_lflag &= !libc::ECHO;
+        }
+        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) == 0
+    }
+}
+
+#[cfg(not(unix))]
+fn set_stdin_echo(_enabled: bool) -> bool {
+    false
+}
+
 #[tokio::main]
 async fn main() {
     let Some(program_options) = parse_arguments() else {
         return;
     };
+    // In non-interactive mode stdout may carry file contents:
+    let interactive = program_options.command.is_empty();
 
-    if let ProgramRole::Server = program_options.program_role {
-        if let Err(error) = server::handle_server(program_options).await {
-            if let QuickTransferError::Other = error {
-            } else {
-                eprintln!("\n{}", error.to_string().red());
-            }
-        }
+    let result = if let ProgramRole::Server = program_options.program_role {
+        server::handle_server(program_options).await
     } else {
         // program_options.program_role == ProgramRole::Client;
 
-        if let Err(error) = client::handle_client(&program_options).await {
-            if let QuickTransferError::Other = error {
-            } else {
-                eprintln!("\n{}", error.to_string().red());
-            }
+        client::handle_client(&program_options).await
+    };
+
+    if let Err(error) = &result {
+        if let QuickTransferError::Other = error {
+        } else {
+            eprintln!("\n{}", error.to_string().red());
         }
     }
 
-    if !cfg!(windows) {
+    if !cfg!(windows) && interactive {
         println!();
     }
+
+    // Scripts (e.g. pipelines uploading stdin) rely on the exit status to detect failures:
+    if result.is_err() {
+        process::exit(1);
+    }
 }
@@ -1,32 +1,62 @@
-use aes_gcm::{aead::KeyInit, Aes256Gcm, Key};
 use colored::*;
 use rustyline_async::{Readline, ReadlineEvent, SharedWriter};
 use std::{
+    ffi::OsStr,
     fs::{self, File},
-    io::{ErrorKind, Write},
-    net::SocketAddr,
+    io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+    net::{IpAddr, SocketAddr},
     ops::Deref,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
     },
+    time::{Duration, Instant},
 };
 use tokio::{
-    net::TcpListener,
+    net::{TcpListener, TcpStream},
     sync::broadcast::{self, Receiver, Sender},
+    task,
+    time::{self, MissedTickBehavior},
 };
+use tokio_rustls::TlsAcceptor;
 
 use crate::common::{
-    directory_description,
+    archive::TarCompression,
+    checksum::{spawn_file_digest, to_hex, ChecksumAlgorithm},
+    connection_description, detailed_directory_description, directory_description,
+    disk::{disk_space, disk_usage, total_usage},
+    file_metadata,
+    follow::{FollowedFile, FOLLOW_POLL_INTERVAL},
+    key_exchange::{
+        random_challenge, HandshakeTranscript, KeyExchange, CHALLENGE_LENGTH, PROOF_LENGTH,
+        PUBLIC_KEY_LENGTH,
+    },
     messages::{
-        CdAnswer, FileFail, MkdirAnswer, RemoveAnswer, RenameAnswer, UploadResult, MESSAGE_CD,
-        MESSAGE_CDANSWER, MESSAGE_DISCONNECT, MESSAGE_DOWNLOAD, MESSAGE_DOWNLOAD_FAIL,
-        MESSAGE_INIT, MESSAGE_INIT_ENC, MESSAGE_LS, MESSAGE_MKDIR, MESSAGE_MKDIRANS,
-        MESSAGE_NOT_ENC, MESSAGE_OK, MESSAGE_REMOVE, MESSAGE_REMOVE_ANSWER, MESSAGE_RENAME,
-        MESSAGE_RENAME_ANSWER, MESSAGE_UPLOAD, MESSAGE_UPLOAD_RESULT,
+        CdAnswer, ChecksumAnswer, CopyAnswer, DfAnswer, DuAnswer, FileFail, FileFragment,
+        FindQuery, FollowEnd, MkdirAnswer, ReadAnswer, ReadRange, RemoveAnswer, RenameAnswer,
+        StatAnswer, StreamEnd, UploadCheckAnswer, UploadResult, COPY_OVERWRITE, COPY_RECURSIVE,
+        MAX_READ_SIZE, MESSAGE_AUTH, MESSAGE_AUTH_FAIL, MESSAGE_CANCEL, MESSAGE_CD,
+        MESSAGE_CDANSWER, MESSAGE_CHALLENGE, MESSAGE_CHECKSUM, MESSAGE_CHECKSUM_ANSWER,
+        MESSAGE_COPY, MESSAGE_COPY_ANSWER, MESSAGE_DF, MESSAGE_DF_ANSWER, MESSAGE_DIR_LONG,
+        MESSAGE_DISCONNECT, MESSAGE_DOWNLOAD, MESSAGE_DOWNLOAD_FAIL, MESSAGE_DOWNLOAD_TAR,
+        MESSAGE_DU, MESSAGE_DU_ANSWER, MESSAGE_ENCRYPTION_REQUIRED, MESSAGE_FIND, MESSAGE_FOLLOW,
+        MESSAGE_FOLLOW_END, MESSAGE_FOLLOW_UPDATE, MESSAGE_INIT, MESSAGE_INIT_ENC,
+        MESSAGE_INIT_PASSPHRASE, MESSAGE_LOGIN, MESSAGE_LS, MESSAGE_LS_LONG, MESSAGE_MKDIR,
+        MESSAGE_MKDIRANS, MESSAGE_NOT_ENC, MESSAGE_NOT_PASSPHRASE, MESSAGE_OK, MESSAGE_PERMISSIONS,
+        MESSAGE_PERMISSION_DENIED, MESSAGE_READ, MESSAGE_READ_ANSWER, MESSAGE_REMOVE,
+        MESSAGE_REMOVE_ANSWER, MESSAGE_RENAME, MESSAGE_RENAME_ANSWER, MESSAGE_STAT,
+        MESSAGE_STAT_ANSWER, MESSAGE_UPLOAD, MESSAGE_UPLOAD_CHECK, MESSAGE_UPLOAD_CHECK_ANSWER,
+        MESSAGE_UPLOAD_RESULT, MESSAGE_UPLOAD_STREAM, REMOVE_PREVIEW, REMOVE_RECURSIVE,
+        RENAME_OVERWRITE,
     },
-    CommunicationAgent, ProgramOptions, ProgramRole, QuickTransferError, QuickTransferStream,
+    passphrase::{spawn_derive_key, SALT_LENGTH},
+    permissions::{Permission, Permissions},
+    rate_limit::{Admission, HandshakeLimiter},
+    tls::{fingerprint, load_or_generate_certificate, tls_acceptor},
+    users::{spawn_verify_password, UserAccount, UserDatabase, DUMMY_PASSWORD_HASH},
+    without_contents, CommunicationAgent, ProgramOptions, ProgramRole, QuickTransferError,
+    QuickTransferStream, StreamFragment, SymlinkPolicy, Transport,
 };
 
 /// This functions server program run in server mode.
@@ -36,6 +66,12 @@ pub async fn handle_server(program_options: ProgramOptions) -> Result<(), QuickT
         program_options.port, program_options.server_ip_address,
     );
 
+    let tls_acceptor = if program_options.tls {
+        Some(create_tls_acceptor(&program_options)?)
+    } else {
+        None
+    };
+
     let listener = create_a_listener(&program_options).await?;
 
     let timeout = program_options.timeout;
@@ -134,6 +170,9 @@ pub async fn handle_server(program_options: ProgramOptions) -> Result<(), QuickT
         }
     });
 
+    let mut rejected_connections: u64 = 0;
+    let limiter = Arc::new(HandshakeLimiter::new(program_options_arc.rate_limits));
+
     loop {
         tokio::select! {
             message = rx_stop.recv() => {
@@ -144,6 +183,51 @@ pub async fn handle_server(program_options: ProgramOptions) -> Result<(), QuickT
             }
             stream = listener.accept() => {
                 let (stream, client_address) = stream.map_err(|_| QuickTransferError::ConnectionCreation)?;
+
+                // Connections from filtered out addresses are closed before the handshake:
+                if !program_options_arc.address_filter.is_allowed(client_address.ip()) {
+                    drop(stream);
+                    rejected_connections += 1;
+                    writeln!(
+                        writer2.clone(),
+                        "{}",
+                        format!(
+                            "Connection from [{}]:{} rejected by the address filter ({} rejected so far).",
+                            client_address.ip().to_canonical(),
+                            client_address.port(),
+                            rejected_connections
+                        )
+                        .red()
+                    ).map_err(|_| QuickTransferError::Stdout)?;
+                    continue;
+                }
+
+                // Addresses connecting too often or locked out after failed authentications
+                // are closed before the handshake too:
+                match limiter.admit(client_address.ip(), Instant::now()) {
+                    Admission::Allowed => {}
+                    Admission::RateLimited { first } => {
+                        drop(stream);
+                        if first {
+                            writeln!(
+                                writer2.clone(),
+                                "{}",
+                                format!(
+                                    "Connections from [{}] are rejected for exceeding {} connections per minute.",
+                                    client_address.ip().to_canonical(),
+                                    program_options_arc.rate_limits.connections_per_minute
+                                )
+                                .red()
+                            ).map_err(|_| QuickTransferError::Stdout)?;
+                        }
+                        continue;
+                    }
+                    Admission::LockedOut => {
+                        drop(stream);
+                        continue;
+                    }
+                }
+
                 connected_clients2.fetch_add(1, Ordering::Relaxed);
 
                 let program_options_arc = Arc::clone(&program_options_arc);
@@ -151,13 +235,42 @@ pub async fn handle_server(program_options: ProgramOptions) -> Result<(), QuickT
                 let tx_disconnected = tx_disconnected.clone();
                 let mut writer = writer2.clone();
 
-                let stream = QuickTransferStream::new_unencrypted(stream, ProgramRole::Server, timeout);
+                let tls_acceptor = tls_acceptor.clone();
+                let limiter = Arc::clone(&limiter);
 
                 tokio::spawn(async move {
-                    let result = handle_client_as_a_server(stream, client_address, program_options_arc.deref(), tx_disconnected.clone(), rx_stop, &mut writer).await;
+                    let result = match accept_transport(stream, client_address, tls_acceptor, timeout, &mut writer).await {
+                        Ok(stream) => {
+                            let stream = QuickTransferStream::new_unencrypted(stream, ProgramRole::Server, timeout);
+                            handle_client_as_a_server(stream, client_address, program_options_arc.deref(), &limiter, tx_disconnected.clone(), rx_stop, &mut writer).await
+                        }
+                        Err(error) => Err(error),
+                    };
                     if let Err(error) = result {
-                        tx_disconnected.send(true).unwrap();
-                        eprintln!("{}", error);
+                        if let QuickTransferError::AuthenticationFailed = error {
+                            if limiter.record_failure(client_address.ip(), Instant::now()) {
+                                let limits = program_options_arc.rate_limits;
+                                writeln!(
+                                    writer,
+                                    "{}",
+                                    format!(
+                                        "Client [{}] has been locked out for {} seconds after {} failed authentications.",
+                                        client_address.ip().to_canonical(),
+                                        limits.lockout.as_secs(),
+                                        limits.max_failures
+                                    )
+                                    .red()
+                                    .bold()
+                                ).map_err(|_| QuickTransferError::Stdout)?;
+                            }
+                        }
+
+                        // A client refused during the handshake must not stop the server:
+                        let rejected = matches!(error, QuickTransferError::EncryptionRequired | QuickTransferError::ServerDoesNotSupportEncryption | QuickTransferError::ServerDoesNotSupportPassphrase | QuickTransferError::AuthenticationFailed | QuickTransferError::LoginRequired | QuickTransferError::TlsHandshake);
+                        tx_disconnected.send(!rejected).unwrap();
+                        if !rejected {
+                            eprintln!("{}", error);
+                        }
                     }
 
                     Ok::<(), QuickTransferError>(())
@@ -169,6 +282,15 @@ pub async fn handle_server(program_options: ProgramOptions) -> Result<(), QuickT
     Ok(())
 }
 
+/// Records a completed handshake. Failed authentications of the address are forgotten only if
+/// the client has proven the knowledge of the key (or passphrase) or logged in, so that
+/// connections without authentication cannot prevent a lockout.
+fn record_handshake(limiter: &HandshakeLimiter, address: IpAddr, authenticated: bool) {
+    if authenticated {
+        limiter.record_success(address);
+    }
+}
+
 /// Checks whether there are 0 clients and returns whether the server should be stopped.
 fn check_clients_number_and_stop(
     connected_clients: &Arc<AtomicUsize>,
@@ -187,6 +309,138 @@ fn check_clients_number_and_stop(
     Ok(false)
 }
 
+/// Returns the permission needed for a request (checked before the request is handled).
+fn required_permission(header: &str) -> Option<Permission> {
+    match header {
+        MESSAGE_LS | MESSAGE_LS_LONG | MESSAGE_FIND | MESSAGE_STAT | MESSAGE_DU => {
+            Some(Permission::List)
+        }
+        MESSAGE_DOWNLOAD | MESSAGE_DOWNLOAD_TAR | MESSAGE_READ | MESSAGE_FOLLOW
+        | MESSAGE_CHECKSUM => Some(Permission::Download),
+        MESSAGE_UPLOAD | MESSAGE_UPLOAD_STREAM | MESSAGE_UPLOAD_CHECK | MESSAGE_COPY => {
+            Some(Permission::Upload)
+        }
+        MESSAGE_MKDIR => Some(Permission::Mkdir),
+        MESSAGE_RENAME => Some(Permission::Rename),
+        MESSAGE_REMOVE => Some(Permission::Delete),
+        _ => None,
+    }
+}
+
+/// Waits for the client to log in. Returns the user name and the account of the user.
+async fn log_in_client<'a>(
+    agent: &mut CommunicationAgent<'_>,
+    users: &'a UserDatabase,
+    client_name: &str,
+    client_port: u16,
+    writer: &mut SharedWriter,
+) -> Result<(String, &'a UserAccount), QuickTransferError> {
+    agent.send_login_required().await?;
+
+    let message_received = match agent.receive_tcp(false).await {
+        Err(QuickTransferError::RemoteClosedConnection(_)) => {
+            // A client run without a user name disconnects:
+            writeln!(
+                writer,
+                "{}",
+                format!(
+                    "Client [{}]:{} disconnected without logging in.",
+                    client_name, client_port
+                )
+                .red()
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+
+            return Err(QuickTransferError::LoginRequired);
+        }
+        message_received => message_received?,
+    };
+    let message_received = agent.read_message_header_check(&message_received, MESSAGE_LOGIN)?;
+    let (user_name, message_received) = agent.read_length_with_string(message_received)?;
+    let (password, _) = agent.read_length_with_string(message_received)?;
+
+    // The time of the answer must not reveal whether the user exists:
+    let account = users.get(&user_name);
+    let password_hash = account.map_or(DUMMY_PASSWORD_HASH, |account| {
+        account.password_hash.as_str()
+    });
+    if spawn_verify_password(password_hash.to_string(), password).await {
+        if let Some(account) = account {
+            return Ok((user_name, account));
+        }
+    }
+
+    agent.send_login_fail().await?;
+    writeln!(
+        writer,
+        "{}",
+        format!(
+            "Client [{}]:{} failed to log in as `{}`.",
+            client_name, client_port, user_name
+        )
+        .red()
+    )
+    .map_err(|_| QuickTransferError::Stdout)?;
+
+    Err(QuickTransferError::AuthenticationFailed)
+}
+
+/// Loads (or generates on first start) the server's certificate and prints its fingerprint,
+/// which clients can pin.
+fn create_tls_acceptor(
+    program_options: &ProgramOptions,
+) -> Result<TlsAcceptor, QuickTransferError> {
+    let certificate_error = || QuickTransferError::TlsCertificate {
+        directory: program_options.tls_directory.display().to_string(),
+    };
+
+    let (certificate, private_key) = load_or_generate_certificate(&program_options.tls_directory)
+        .map_err(|_| certificate_error())?;
+    println!(
+        "TLS certificate fingerprint (SHA-256): {}",
+        to_hex(&fingerprint(&certificate))
+    );
+
+    tls_acceptor(certificate, private_key).map_err(|_| certificate_error())
+}
+
+/// Starts TLS on a client's connection (if the server uses TLS).
+async fn accept_transport(
+    stream: TcpStream,
+    client_address: SocketAddr,
+    tls_acceptor: Option<TlsAcceptor>,
+    timeout: u16,
+    writer: &mut SharedWriter,
+) -> Result<Box<dyn Transport>, QuickTransferError> {
+    let Some(tls_acceptor) = tls_acceptor else {
+        return Ok(Box::new(stream));
+    };
+
+    match time::timeout(
+        Duration::from_secs(timeout.into()),
+        tls_acceptor.accept(stream),
+    )
+    .await
+    {
+        Ok(Ok(stream)) => Ok(Box::new(stream)),
+        _ => {
+            writeln!(
+                writer,
+                "{}",
+                format!(
+                    "Client [{}]:{} failed the TLS handshake.",
+                    client_address.ip().to_canonical(),
+                    client_address.port()
+                )
+                .red()
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+
+            Err(QuickTransferError::TlsHandshake)
+        }
+    }
+}
+
 /// Creates a TCP listener for server.
 async fn create_a_listener(
     program_options: &ProgramOptions,
@@ -206,6 +460,7 @@ async fn handle_client_as_a_server(
     mut stream: QuickTransferStream,
     client_address: SocketAddr,
     program_options: &ProgramOptions,
+    limiter: &HandshakeLimiter,
     tx_disconnected: Sender<bool>,
     mut rx_stop: Receiver<(bool, bool)>,
     writer: &mut SharedWriter,
@@ -214,19 +469,73 @@ async fn handle_client_as_a_server(
     let mut agent =
         CommunicationAgent::new(&mut stream, ProgramRole::Server, program_options.timeout);
 
-    let mut is_connection_encrypted = false;
+    // The pre-shared key (if any), the client's ephemeral public key and challenge:
+    let mut client_public_key = [0_u8; PUBLIC_KEY_LENGTH];
+    let mut client_challenge = [0_u8; CHALLENGE_LENGTH];
+    let client_name = client_address.ip().to_canonical().to_string();
+    let client_port = client_address.port();
 
-    match agent.receive_bare_message_header().await?.as_str() {
-        MESSAGE_INIT => {}
+    let pre_shared_key = match agent.receive_bare_message_header().await?.as_str() {
+        MESSAGE_INIT if program_options.require_encryption => {
+            agent.send_bare_message(MESSAGE_ENCRYPTION_REQUIRED).await?;
+            writeln!(
+                writer,
+                "{}",
+                format!(
+                    "Client [{}]:{} tried to connect without encryption and was rejected.",
+                    client_name, client_port
+                )
+                .red()
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+
+            return Err(QuickTransferError::EncryptionRequired);
+        }
+        MESSAGE_INIT => None,
         MESSAGE_INIT_ENC => {
+            agent.receive_bare_data(&mut client_public_key).await?;
+            agent.receive_bare_data(&mut client_challenge).await?;
+
             if let Some(key) = &program_options.aes_key {
-                let key: &Key<Aes256Gcm> = key.into();
-                let cipher = Aes256Gcm::new(key);
-                agent.change_to_encrypted(cipher);
-                is_connection_encrypted = true;
+                Some(*key)
             } else {
                 agent.send_bare_message(MESSAGE_NOT_ENC).await?;
-                return Err(QuickTransferError::Fatal); // TODO
+                writeln!(
+                    writer,
+                    "{}",
+                    format!(
+                        "Client [{}]:{} tried to connect with a key and was rejected.",
+                        client_name, client_port
+                    )
+                    .red()
+                )
+                .map_err(|_| QuickTransferError::Stdout)?;
+
+                return Err(QuickTransferError::ServerDoesNotSupportEncryption);
+            }
+        }
+        MESSAGE_INIT_PASSPHRASE => {
+            let mut salt = [0_u8; SALT_LENGTH];
+            agent.receive_bare_data(&mut salt).await?;
+            agent.receive_bare_data(&mut client_public_key).await?;
+            agent.receive_bare_data(&mut client_challenge).await?;
+
+            if let Some(passphrase) = &program_options.passphrase {
+                Some(spawn_derive_key(passphrase.clone(), salt).await?)
+            } else {
+                agent.send_bare_message(MESSAGE_NOT_PASSPHRASE).await?;
+                writeln!(
+                    writer,
+                    "{}",
+                    format!(
+                        "Client [{}]:{} tried to connect with a passphrase and was rejected.",
+                        client_name, client_port
+                    )
+                    .red()
+                )
+                .map_err(|_| QuickTransferError::Stdout)?;
+
+                return Err(QuickTransferError::ServerDoesNotSupportPassphrase);
             }
         }
         _ => {
@@ -234,25 +543,104 @@ async fn handle_client_as_a_server(
                 program_options.program_role,
             ));
         }
-    }
+    };
+    let is_connection_encrypted = pre_shared_key.is_some();
+
+    if let Some(pre_shared_key) = pre_shared_key {
+        // Only the ephemeral session key is used for encrypting the connection:
+        let key_exchange = KeyExchange::new();
+        let server_challenge = random_challenge();
+        agent.send_bare_message(MESSAGE_CHALLENGE).await?;
+        agent.send_bare_data(&key_exchange.public_key()).await?;
+        agent.send_bare_data(&server_challenge).await?;
+
+        // Client proves the knowledge of the key first, so that a client not knowing it
+        // does not learn anything derived from the key:
+        let transcript = HandshakeTranscript {
+            client_public_key,
+            client_challenge,
+            server_public_key: key_exchange.public_key(),
+            server_challenge,
+        };
+        if agent.receive_bare_message_header().await? != MESSAGE_AUTH {
+            return Err(QuickTransferError::SentInvalidData(ProgramRole::Server));
+        }
+        let mut client_proof = [0_u8; PROOF_LENGTH];
+        agent.receive_bare_data(&mut client_proof).await?;
+
+        if !transcript.verify(&pre_shared_key, ProgramRole::Client, &client_proof) {
+            agent.send_bare_message(MESSAGE_AUTH_FAIL).await?;
+            writeln!(
+                writer,
+                "{}",
+                format!(
+                    "Client [{}]:{} failed to authenticate.",
+                    client_name, client_port
+                )
+                .red()
+            )
+            .map_err(|_| QuickTransferError::Stdout)?;
+
+            return Err(QuickTransferError::AuthenticationFailed);
+        }
 
-    let client_name = client_address.ip().to_canonical().to_string();
-    let client_port = client_address.port();
+        agent.send_bare_message(MESSAGE_OK).await?;
+        agent
+            .send_bare_data(&transcript.proof(&pre_shared_key, ProgramRole::Server))
+            .await?;
+
+        let session_keys =
+            key_exchange.session_keys(&pre_shared_key, &client_public_key, ProgramRole::Server)?;
+        agent.change_to_encrypted(&session_keys, program_options.rekey_limits);
+    } else {
+        agent.send_bare_message(MESSAGE_OK).await?;
+    }
 
-    agent.send_bare_message(MESSAGE_OK).await?;
+    // Clients of a server with user accounts have to log in and are confined to their
+    // own root directories:
+    let (user_name, user_root_directory, permissions) = match &program_options.users {
+        Some(users) => {
+            let (user_name, account) =
+                log_in_client(&mut agent, users, &client_name, client_port, writer).await?;
+            (
+                Some(user_name),
+                account.root_directory.clone(),
+                account.permissions.unwrap_or(program_options.permissions),
+            )
+        }
+        None => (
+            None,
+            PathBuf::from(&program_options.root_directory),
+            program_options.permissions,
+        ),
+    };
+    record_handshake(
+        limiter,
+        client_address.ip(),
+        is_connection_encrypted || user_name.is_some(),
+    );
+    agent.send_answer(MESSAGE_PERMISSIONS, &permissions).await?;
 
     writeln!(
         writer,
-        "{}{}{}{}{}",
+        "{}{}{}{}{}{}{}",
         "A new client (".green().bold(),
         format!("[{}]:{}", client_name, client_port)
             .on_green()
             .white(),
+        user_name
+            .map(|user_name| format!(", user `{}`", user_name))
+            .unwrap_or_default()
+            .green()
+            .bold(),
         ") has connected! (connection ".green().bold(),
-        if is_connection_encrypted {
-            "encrypted"
+        connection_description(program_options.tls, is_connection_encrypted)
+            .green()
+            .bold(),
+        if permissions == Permissions::FULL {
+            String::new()
         } else {
-            "not encrypted"
+            format!(", permissions: {}", permissions.names())
         }
         .green()
         .bold(),
@@ -261,12 +649,20 @@ async fn handle_client_as_a_server(
     .map_err(|_| QuickTransferError::Stdout)?;
 
     let mut current_path = PathBuf::new();
-    current_path.push(&program_options.root_directory);
+    current_path.push(&user_root_directory);
     current_path = current_path.canonicalize().unwrap();
     let root_directory = current_path.as_path().canonicalize().unwrap();
+    let symlink_policy = program_options.symlink_policy;
+
+    let list_contents = permissions.allows(Permission::List);
 
     agent
-        .send_directory_description(&current_path, &root_directory)
+        .send_directory_description(
+            &current_path,
+            &root_directory,
+            symlink_policy,
+            list_contents,
+        )
         .await?;
 
     loop {
@@ -285,48 +681,71 @@ async fn handle_client_as_a_server(
                 let message_received = message_received?;
                 let (header_received, message_received) = agent.read_message_header(&message_received)?;
 
+                // Permissions are checked before a request is handled:
+                if let Some(permission) = required_permission(&header_received) {
+                    if !permissions.allows(permission) {
+                        if header_received == MESSAGE_UPLOAD_STREAM {
+                            // The stream follows the request without waiting for an answer:
+                            while let StreamFragment::Data(_) = agent.receive_stream_fragment(true).await? {}
+                        }
+                        agent.send_answer(MESSAGE_PERMISSION_DENIED, &permission).await?;
+                        continue;
+                    }
+                }
+
                 match header_received.as_str() {
                     MESSAGE_CD => {
                         let (dir_name, _) = agent.read_length_with_string(message_received)?;
-                        let mut next_path = current_path.to_path_buf();
-                        next_path.push(dir_name);
 
-                        if !fs::exists(next_path.as_path()).unwrap_or(false) || !next_path.as_path().is_dir() {
-                            agent.send_answer(MESSAGE_CDANSWER, &CdAnswer::DirectoryDoesNotExist).await?;
-                            continue;
-                        }
+                        let next_path = match resolve_existing_path(&current_path, &root_directory, &dir_name, symlink_policy, true) {
+                            ResolvedPath::Existing(next_path) if next_path.is_dir() => next_path,
+                            ResolvedPath::Illegal => {
+                                agent.send_answer(MESSAGE_CDANSWER, &CdAnswer::IllegalDirectory).await?;
+                                continue;
+                            }
+                            _ => {
+                                agent.send_answer(MESSAGE_CDANSWER, &CdAnswer::DirectoryDoesNotExist).await?;
+                                continue;
+                            }
+                        };
 
-                        let next_path = next_path.canonicalize().unwrap();
-                        if !next_path.starts_with(root_directory.clone()) || next_path == current_path {
+                        if next_path == current_path {
                             agent.send_answer(MESSAGE_CDANSWER, &CdAnswer::IllegalDirectory).await?;
                             continue;
                         }
 
                         current_path = next_path;
 
-                        let Ok(directory_contents) = directory_description(&current_path, &root_directory) else {
+                        let Ok(mut directory_contents) = directory_description(&current_path, &root_directory, symlink_policy) else {
                             agent.send_answer(MESSAGE_CDANSWER, &CdAnswer::ReadingDirectoryError).await?;
                             continue;
                         };
+                        if !list_contents {
+                            directory_contents = without_contents(directory_contents);
+                        }
                         agent.send_answer(MESSAGE_CDANSWER, &CdAnswer::Success(directory_contents)).await?;
                     }
                     MESSAGE_LS => {
-                        agent.send_directory_description(&current_path, &root_directory).await?;
+                        agent.send_directory_description(&current_path, &root_directory, symlink_policy, list_contents).await?;
+                    }
+                    MESSAGE_LS_LONG => {
+                        let directory_contents = detailed_directory_description(&current_path, &root_directory, symlink_policy);
+                        agent.send_answer(MESSAGE_DIR_LONG, &directory_contents).await?;
                     }
                     MESSAGE_DOWNLOAD => {
                         let (file_name, _) = agent.read_length_with_string(message_received)?;
-                        let mut file_path = current_path.to_path_buf();
-                        file_path.push(file_name);
-
-                        if !file_path.starts_with(root_directory.clone()) {
-                            agent.send_answer(MESSAGE_DOWNLOAD_FAIL, &FileFail::IllegalFile).await?;
-                            continue;
-                        }
 
-                        if !fs::exists(file_path.as_path()).unwrap() || !file_path.as_path().is_file() {
-                            agent.send_answer(MESSAGE_DOWNLOAD_FAIL, &FileFail::FileDoesNotExist).await?;
-                            continue;
-                        }
+                        let file_path = match resolve_existing_path(&current_path, &root_directory, &file_name, symlink_policy, true) {
+                            ResolvedPath::Existing(file_path) if file_path.is_file() => file_path,
+                            ResolvedPath::Illegal => {
+                                agent.send_answer(MESSAGE_DOWNLOAD_FAIL, &FileFail::IllegalFile).await?;
+                                continue;
+                            }
+                            _ => {
+                                agent.send_answer(MESSAGE_DOWNLOAD_FAIL, &FileFail::FileDoesNotExist).await?;
+                                continue;
+                            }
+                        };
 
                         let Ok(opened_file) = File::open(&file_path) else {
                             agent.send_answer(MESSAGE_DOWNLOAD_FAIL, &FileFail::ErrorOpeningFile).await?;
@@ -338,42 +757,358 @@ async fn handle_client_as_a_server(
                         agent.send_download_success(file_size).await?;
                         agent.send_file(opened_file, file_size, &file_path).await?;
                     }
+                    MESSAGE_DOWNLOAD_TAR => {
+                        let (directory_name, message_received) = agent.read_length_with_string(message_received)?;
+                        let (compression, _) = agent.read_u64(message_received)?;
+                        let Some(compression) = TarCompression::from_u64(compression) else {
+                            return Err(QuickTransferError::SentInvalidData(ProgramRole::Server));
+                        };
+
+                        let directory_path = match resolve_existing_path(&current_path, &root_directory, &directory_name, symlink_policy, true) {
+                            ResolvedPath::Existing(directory_path) if directory_path.is_dir() => directory_path,
+                            ResolvedPath::Illegal => {
+                                agent.send_answer(MESSAGE_DOWNLOAD_FAIL, &FileFail::IllegalFile).await?;
+                                continue;
+                            }
+                            _ => {
+                                agent.send_answer(MESSAGE_DOWNLOAD_FAIL, &FileFail::FileDoesNotExist).await?;
+                                continue;
+                            }
+                        };
+
+                        let archive_name = directory_path
+                            .file_name()
+                            .and_then(|name| name.to_str())
+                            .unwrap_or("root")
+                            .to_string();
+
+                        agent.send_tar_stream(&directory_path, &archive_name, compression).await?;
+                    }
+                    MESSAGE_UPLOAD_CHECK => {
+                        let (file_name, message_received) = agent.read_length_with_string(message_received)?;
+                        let (file_size, message_received) = agent.read_u64(message_received)?;
+                        let (algorithm, _) = agent.read_u64(message_received)?;
+                        let Some(algorithm) = ChecksumAlgorithm::from_u64(algorithm) else {
+                            return Err(QuickTransferError::SentInvalidData(ProgramRole::Server));
+                        };
+                        let file_name_truncated = Path::new(&file_name).file_name().map(|string| string.to_str().map(|string| string.to_string())).unwrap_or(Some(file_name.clone())).unwrap_or(file_name.clone());
+
+                        // The destination is resolved the same way as by "UPLOAD". A digest reveals
+                        // the contents of the file, so it is sent only to clients that may download it:
+                        let check_answer = match resolve_written_file(&current_path, &root_directory, &file_name_truncated, symlink_policy) {
+                            ResolvedDestination::Illegal => UploadCheckAnswer::IllegalFile,
+                            ResolvedDestination::ParentDoesNotExist => UploadCheckAnswer::Missing,
+                            ResolvedDestination::Path(file_path) => match fs::metadata(&file_path) {
+                                Ok(metadata) if metadata.is_file() && metadata.len() == file_size && !permissions.allows(Permission::Download) => {
+                                    UploadCheckAnswer::DigestNotPermitted
+                                }
+                                Ok(metadata) if metadata.is_file() && metadata.len() == file_size => {
+                                    let (_, digest) = spawn_file_digest(file_path, algorithm);
+                                    match digest.await {
+                                        Ok(Ok(digest)) => UploadCheckAnswer::Digest(digest),
+                                        _ => UploadCheckAnswer::ErrorReading,
+                                    }
+                                }
+                                Ok(metadata) if metadata.is_file() => UploadCheckAnswer::DifferentSize,
+                                _ => UploadCheckAnswer::Missing,
+                            },
+                        };
+                        agent.send_answer(MESSAGE_UPLOAD_CHECK_ANSWER, &check_answer).await?;
+                    }
                     MESSAGE_UPLOAD => {
                         let (file_name, message_received) = agent.read_length_with_string(message_received)?;
                         let (file_size, _) = agent.read_u64(message_received)?;
-                        let mut file_path = current_path.to_path_buf();
                         let file_name_truncated = Path::new(&file_name).file_name().map(|string| string.to_str().map(|string| string.to_string())).unwrap_or(Some(file_name.clone())).unwrap_or(file_name.clone());
-                        file_path.push(&file_name_truncated);
 
+                        let file_path = match resolve_written_file(&current_path, &root_directory, &file_name_truncated, symlink_policy) {
+                            ResolvedDestination::Path(file_path) => file_path,
+                            ResolvedDestination::Illegal => {
+                                agent.send_answer(MESSAGE_UPLOAD_RESULT, &UploadResult::Fail(FileFail::IllegalFile)).await?;
+                                continue;
+                            }
+                            ResolvedDestination::ParentDoesNotExist => {
+                                agent.send_answer(MESSAGE_UPLOAD_RESULT, &UploadResult::Fail(FileFail::ErrorCreatingFile)).await?;
+                                continue;
+                            }
+                        };
                         let file_path = file_path.as_path();
-                        let opened_file = File::create(file_path);
 
-                        let mut fail = false;
-                        if opened_file.is_err() {
-                            fail = true;
+                        if !permissions.allows(Permission::Overwrite) && fs::symlink_metadata(file_path).is_ok() {
+                            agent.send_answer(MESSAGE_PERMISSION_DENIED, &Permission::Overwrite).await?;
+                            continue;
+                        }
+
+                        // An overwritten file frees its space:
+                        let overwritten_size = fs::symlink_metadata(file_path).map(|metadata| metadata.len()).unwrap_or(0);
+                        if disk_space(&current_path).is_ok_and(|space| space.available.saturating_add(overwritten_size) < file_size) {
+                            agent.send_answer(MESSAGE_UPLOAD_RESULT, &UploadResult::Fail(FileFail::NotEnoughSpace)).await?;
+                            continue;
+                        }
+
+                        let Ok(opened_file) = File::create(file_path) else {
                             agent.send_answer(MESSAGE_UPLOAD_RESULT, &UploadResult::Fail(FileFail::ErrorCreatingFile)).await?;
+                            continue;
+                        };
+
+                        agent.send_upload_accept().await?;
+
+                        let upload_result = if agent.receive_file(opened_file, file_size, file_path, true).await? {
+                            UploadResult::Success
+                        } else {
+                            UploadResult::Fail(FileFail::ErrorWritingFile)
+                        };
+                        agent.send_answer(MESSAGE_UPLOAD_RESULT, &upload_result).await?;
+                    }
+                    MESSAGE_UPLOAD_STREAM => {
+                        let (file_name, _) = agent.read_length_with_string(message_received)?;
+                        let file_name_truncated = Path::new(&file_name).file_name().map(|string| string.to_str().map(|string| string.to_string())).unwrap_or(Some(file_name.clone())).unwrap_or(file_name.clone());
+
+                        let mut overwrite_denied = false;
+                        let (mut opened_file, mut upload_result) = match resolve_written_file(&current_path, &root_directory, &file_name_truncated, symlink_policy) {
+                            ResolvedDestination::Path(file_path) if !permissions.allows(Permission::Overwrite) && fs::symlink_metadata(&file_path).is_ok() => {
+                                overwrite_denied = true;
+                                (None, UploadResult::Fail(FileFail::ErrorCreatingFile))
+                            }
+                            ResolvedDestination::Path(file_path) => match File::create(&file_path) {
+                                Ok(opened_file) => (Some(opened_file), UploadResult::Success),
+                                Err(_) => (None, UploadResult::Fail(FileFail::ErrorCreatingFile)),
+                            },
+                            ResolvedDestination::Illegal => (None, UploadResult::Fail(FileFail::IllegalFile)),
+                            ResolvedDestination::ParentDoesNotExist => (None, UploadResult::Fail(FileFail::ErrorCreatingFile)),
+                        };
+
+                        // The stream has to be received completely even if saving fails:
+                        loop {
+                            match agent.receive_stream_fragment(true).await? {
+                                StreamFragment::Data(data) => {
+                                    if let Some(file) = &mut opened_file {
+                                        if file.write_all(&data).is_err() {
+                                            opened_file = None;
+                                            upload_result = UploadResult::Fail(FileFail::ErrorWritingFile);
+                                        }
+                                    }
+                                }
+                                StreamFragment::End(stream_end) => {
+                                    if stream_end == StreamEnd::Fail && upload_result == UploadResult::Success {
+                                        upload_result = UploadResult::Fail(FileFail::TransferInterrupted);
+                                    }
+                                    break;
+                                }
+                            }
                         }
 
-                        agent.receive_file(opened_file.unwrap(), file_size, file_path, !fail).await?;
-                        if !fail {
-                            agent.send_answer(MESSAGE_UPLOAD_RESULT, &UploadResult::Success).await?;
+                        if overwrite_denied {
+                            agent.send_answer(MESSAGE_PERMISSION_DENIED, &Permission::Overwrite).await?;
+                            continue;
                         }
+                        agent.send_answer(MESSAGE_UPLOAD_RESULT, &upload_result).await?;
                     }
-                    MESSAGE_MKDIR => {
-                        let (directory_name, _) = agent.read_length_with_string(message_received)?;
-                        let mut next_path = current_path.to_path_buf();
-                        next_path.push(&directory_name);
+                    MESSAGE_READ => {
+                        let (file_name, message_received) = agent.read_length_with_string(message_received)?;
+                        let range: ReadRange = agent.read_answer(message_received)?;
 
-                        if fs::exists(next_path.as_path()).unwrap() {
-                            agent.send_answer(MESSAGE_MKDIRANS, &MkdirAnswer::DirectoryAlreadyExists).await?;
+                        let file_path = match resolve_existing_path(&current_path, &root_directory, &file_name, symlink_policy, true) {
+                            ResolvedPath::DoesNotExist => {
+                                agent.send_answer(MESSAGE_READ_ANSWER, &ReadAnswer::FileDoesNotExist).await?;
+                                continue;
+                            }
+                            ResolvedPath::Illegal => {
+                                agent.send_answer(MESSAGE_READ_ANSWER, &ReadAnswer::IllegalFile).await?;
+                                continue;
+                            }
+                            ResolvedPath::Existing(file_path) => file_path,
+                        };
+
+                        if !file_path.is_file() {
+                            agent.send_answer(MESSAGE_READ_ANSWER, &ReadAnswer::FileDoesNotExist).await?;
                             continue;
                         }
 
-                        if !next_path.starts_with(root_directory.clone()) || next_path == current_path {
-                            agent.send_answer(MESSAGE_MKDIRANS, &MkdirAnswer::IllegalDirectory).await?;
+                        let read_answer = File::open(&file_path)
+                            .and_then(|mut file| read_file_fragment(&mut file, &range))
+                            .map(ReadAnswer::Success)
+                            .unwrap_or(ReadAnswer::ErrorReading);
+                        agent.send_answer(MESSAGE_READ_ANSWER, &read_answer).await?;
+                    }
+                    MESSAGE_FIND => {
+                        let query: FindQuery = agent.read_answer(message_received)?;
+
+                        agent.send_find_results(&current_path, query).await?;
+                    }
+                    MESSAGE_DU => {
+                        let (path_name, message_received) = agent.read_length_with_string(message_received)?;
+                        let (summarize, _) = agent.read_u64(message_received)?;
+
+                        let displayed_path = if path_name.is_empty() { String::from(".") } else { path_name.clone() };
+                        let du_answer = match resolve_existing_path(&current_path, &root_directory, &path_name, symlink_policy, true) {
+                            ResolvedPath::DoesNotExist => DuAnswer::FileDirDoesNotExist,
+                            ResolvedPath::Illegal => DuAnswer::IllegalFileDir,
+                            ResolvedPath::Existing(path) => {
+                                // A symbolic link given as the path is followed (the policy allows it):
+                                task::spawn_blocking(move || {
+                                    fs::canonicalize(&path).and_then(|path| disk_usage(&path, &displayed_path, summarize != 0))
+                                })
+                                    .await
+                                    .ok()
+                                    .and_then(Result::ok)
+                                    .map(DuAnswer::Success)
+                                    .unwrap_or(DuAnswer::ErrorReading)
+                            }
+                        };
+                        agent.send_answer(MESSAGE_DU_ANSWER, &du_answer).await?;
+                    }
+                    MESSAGE_DF => {
+                        let df_answer = disk_space(&root_directory)
+                            .map(DfAnswer::Success)
+                            .unwrap_or(DfAnswer::ErrorReading);
+                        agent.send_answer(MESSAGE_DF_ANSWER, &df_answer).await?;
+                    }
+                    MESSAGE_CHECKSUM => {
+                        let (file_name, message_received) = agent.read_length_with_string(message_received)?;
+                        let (algorithm, _) = agent.read_u64(message_received)?;
+                        let Some(algorithm) = ChecksumAlgorithm::from_u64(algorithm) else {
+                            return Err(QuickTransferError::SentInvalidData(ProgramRole::Server));
+                        };
+
+                        let file_path = match resolve_existing_path(&current_path, &root_directory, &file_name, symlink_policy, true) {
+                            ResolvedPath::DoesNotExist => {
+                                agent.send_answer(MESSAGE_CHECKSUM_ANSWER, &ChecksumAnswer::FileDoesNotExist).await?;
+                                continue;
+                            }
+                            ResolvedPath::Illegal => {
+                                agent.send_answer(MESSAGE_CHECKSUM_ANSWER, &ChecksumAnswer::IllegalFile).await?;
+                                continue;
+                            }
+                            ResolvedPath::Existing(file_path) => file_path,
+                        };
+
+                        if !file_path.is_file() {
+                            agent.send_answer(MESSAGE_CHECKSUM_ANSWER, &ChecksumAnswer::FileDoesNotExist).await?;
                             continue;
                         }
 
+                        // The client may cancel the computation while it is running:
+                        let (cancelled, mut digest) = spawn_file_digest(file_path, algorithm);
+                        let digest = tokio::select! {
+                            digest = &mut digest => digest,
+                            message = agent.wait_for_message() => {
+                                message?;
+                                let message = agent.receive_tcp(false).await?;
+                                let (header, _) = agent.read_message_header(&message)?;
+                                if header != MESSAGE_CANCEL {
+                                    return Err(QuickTransferError::SentInvalidData(ProgramRole::Server));
+                                }
+
+                                cancelled.store(true, Ordering::Relaxed);
+                                digest.await
+                            }
+                        };
+
+                        let checksum_answer = match digest {
+                            Ok(Ok(digest)) => ChecksumAnswer::Success(digest),
+                            Ok(Err(error)) if error.kind() == ErrorKind::Interrupted => ChecksumAnswer::Cancelled,
+                            _ => ChecksumAnswer::ErrorReading,
+                        };
+                        agent.send_answer(MESSAGE_CHECKSUM_ANSWER, &checksum_answer).await?;
+                    }
+                    MESSAGE_FOLLOW => {
+                        let (file_name, message_received) = agent.read_length_with_string(message_received)?;
+                        let (offset, _) = agent.read_u64(message_received)?;
+
+                        let file_path = match resolve_existing_path(&current_path, &root_directory, &file_name, symlink_policy, true) {
+                            ResolvedPath::Existing(file_path) if file_path.is_file() => file_path,
+                            ResolvedPath::Illegal => {
+                                agent.send_answer(MESSAGE_FOLLOW_END, &FollowEnd::IllegalFile).await?;
+                                continue;
+                            }
+                            _ => {
+                                agent.send_answer(MESSAGE_FOLLOW_END, &FollowEnd::FileDoesNotExist).await?;
+                                continue;
+                            }
+                        };
+
+                        let Ok(mut followed_file) = FollowedFile::open(&file_path, offset) else {
+                            agent.send_answer(MESSAGE_FOLLOW_END, &FollowEnd::ErrorReading).await?;
+                            continue;
+                        };
+
+                        // The file is followed until the client cancels it:
+                        let mut poll_interval = time::interval(FOLLOW_POLL_INTERVAL);
+                        poll_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                        let follow_end = loop {
+                            tokio::select! {
+                                _ = poll_interval.tick() => {
+                                    // The path is resolved again, as the file may have been replaced:
+                                    let current_file_path = match resolve_existing_path(&current_path, &root_directory, &file_name, symlink_policy, true) {
+                                        ResolvedPath::Existing(file_path) => Some(file_path),
+                                        _ => None,
+                                    };
+
+                                    let Ok(updates) = followed_file.poll(current_file_path.as_deref()) else {
+                                        break FollowEnd::ErrorReading;
+                                    };
+                                    for update in updates {
+                                        agent.send_answer(MESSAGE_FOLLOW_UPDATE, &update).await?;
+                                    }
+                                }
+                                message = agent.wait_for_message() => {
+                                    message?;
+                                    let message = agent.receive_tcp(false).await?;
+                                    let (header, _) = agent.read_message_header(&message)?;
+                                    if header != MESSAGE_CANCEL {
+                                        return Err(QuickTransferError::SentInvalidData(ProgramRole::Server));
+                                    }
+
+                                    break FollowEnd::Stopped;
+                                }
+                                message = rx_stop.recv() => {
+                                    let message = message.unwrap();
+
+                                    if !message.0 {
+                                        agent.send_disconnect_message().await?;
+                                        tx_disconnected.send(message.1).unwrap();
+
+                                        return Ok(());
+                                    }
+                                }
+                            }
+                        };
+                        agent.send_answer(MESSAGE_FOLLOW_END, &follow_end).await?;
+                    }
+                    MESSAGE_CANCEL => {
+                        // The cancelled operation has already finished.
+                    }
+                    MESSAGE_STAT => {
+                        let (file_dir_name, _) = agent.read_length_with_string(message_received)?;
+
+                        let stat_answer = match resolve_existing_path(&current_path, &root_directory, &file_dir_name, symlink_policy, false) {
+                            ResolvedPath::DoesNotExist => StatAnswer::FileDirDoesNotExist,
+                            ResolvedPath::Illegal => StatAnswer::IllegalFileDir,
+                            ResolvedPath::Existing(path) => file_metadata(&path, &root_directory, symlink_policy)
+                                .map(StatAnswer::Success)
+                                .unwrap_or(StatAnswer::ErrorReadingMetadata),
+                        };
+                        agent.send_answer(MESSAGE_STAT_ANSWER, &stat_answer).await?;
+                    }
+                    MESSAGE_MKDIR => {
+                        let (directory_name, _) = agent.read_length_with_string(message_received)?;
+
+                        let next_path = match resolve_new_path(lexical_path(&current_path, &directory_name), &root_directory, symlink_policy) {
+                            ResolvedDestination::Path(next_path) => next_path,
+                            ResolvedDestination::Illegal => {
+                                agent.send_answer(MESSAGE_MKDIRANS, &MkdirAnswer::IllegalDirectory).await?;
+                                continue;
+                            }
+                            ResolvedDestination::ParentDoesNotExist => {
+                                agent.send_answer(MESSAGE_MKDIRANS, &MkdirAnswer::ErrorCreatingDirectory).await?;
+                                continue;
+                            }
+                        };
+
+                        if fs::symlink_metadata(&next_path).is_ok() {
+                            agent.send_answer(MESSAGE_MKDIRANS, &MkdirAnswer::DirectoryAlreadyExists).await?;
+                            continue;
+                        }
 
                         if fs::create_dir(&next_path).is_err() {
                             agent.send_answer(MESSAGE_MKDIRANS, &MkdirAnswer::ErrorCreatingDirectory).await?;
@@ -384,47 +1119,179 @@ async fn handle_client_as_a_server(
                     }
                     MESSAGE_RENAME => {
                         let (file_dir_name, message_received) = agent.read_length_with_string(message_received)?;
-                        let (new_name, _) = agent.read_length_with_string(message_received)?;
+                        let (new_name, message_received) = agent.read_length_with_string(message_received)?;
+                        let (flags, _) = agent.read_u64(message_received)?;
 
-                        let mut file_path = current_path.to_path_buf();
-                        file_path.push(&file_dir_name);
+                        // A symbolic link is moved itself, not its target:
+                        let source = match resolve_existing_path(&current_path, &root_directory, &file_dir_name, symlink_policy, false) {
+                            ResolvedPath::DoesNotExist => {
+                                agent.send_answer(MESSAGE_RENAME_ANSWER, &RenameAnswer::FileDirDoesNotExist).await?;
+                                continue;
+                            }
+                            ResolvedPath::Existing(source) if source != root_directory => source,
+                            _ => {
+                                agent.send_answer(MESSAGE_RENAME_ANSWER, &RenameAnswer::IllegalFileDir).await?;
+                                continue;
+                            }
+                        };
 
-                        if !fs::exists(file_path.as_path()).unwrap() {
-                            agent.send_answer(MESSAGE_RENAME_ANSWER, &RenameAnswer::FileDirDoesNotExist).await?;
+                        let source_name = source.file_name().unwrap_or_default();
+                        let destination = match resolve_destination(&current_path, &root_directory, &new_name, source_name, symlink_policy) {
+                            ResolvedDestination::ParentDoesNotExist => {
+                                agent.send_answer(MESSAGE_RENAME_ANSWER, &RenameAnswer::DestinationDoesNotExist).await?;
+                                continue;
+                            }
+                            ResolvedDestination::Illegal => {
+                                agent.send_answer(MESSAGE_RENAME_ANSWER, &RenameAnswer::IllegalDestination).await?;
+                                continue;
+                            }
+                            ResolvedDestination::Path(destination) => destination,
+                        };
+
+                        if destination == source {
+                            agent.send_answer(MESSAGE_RENAME_ANSWER, &RenameAnswer::Success).await?;
                             continue;
                         }
 
-                        let current = file_path.canonicalize().unwrap();
-                        if !current.starts_with(root_directory.clone()) {
-                            agent.send_answer(MESSAGE_RENAME_ANSWER, &RenameAnswer::IllegalFileDir).await?;
+                        if is_inside(&destination, &source) {
+                            agent.send_answer(MESSAGE_RENAME_ANSWER, &RenameAnswer::DestinationInsideSource).await?;
                             continue;
                         }
 
-                        if fs::rename(&file_path, new_name).is_err() {
+                        if fs::symlink_metadata(&destination).is_ok() {
+                            if flags & RENAME_OVERWRITE == 0 {
+                                agent.send_answer(MESSAGE_RENAME_ANSWER, &RenameAnswer::DestinationExists).await?;
+                                continue;
+                            }
+                            if !permissions.allows(Permission::Overwrite) {
+                                agent.send_answer(MESSAGE_PERMISSION_DENIED, &Permission::Overwrite).await?;
+                                continue;
+                            }
+                        }
+
+                        if fs::rename(&source, &destination).is_err() {
                             agent.send_answer(MESSAGE_RENAME_ANSWER, &RenameAnswer::ErrorRenaming).await?;
                             continue;
                         }
 
                         agent.send_answer(MESSAGE_RENAME_ANSWER, &RenameAnswer::Success).await?;
                     }
+                    MESSAGE_COPY => {
+                        let (source_name, message_received) = agent.read_length_with_string(message_received)?;
+                        let (destination_name, message_received) = agent.read_length_with_string(message_received)?;
+                        let (flags, _) = agent.read_u64(message_received)?;
+                        let overwrite = flags & COPY_OVERWRITE != 0;
+
+                        // A symbolic link is copied as a link:
+                        let source = match resolve_existing_path(&current_path, &root_directory, &source_name, symlink_policy, false) {
+                            ResolvedPath::DoesNotExist => {
+                                agent.send_answer(MESSAGE_COPY_ANSWER, &CopyAnswer::FileDirDoesNotExist).await?;
+                                continue;
+                            }
+                            ResolvedPath::Illegal => {
+                                agent.send_answer(MESSAGE_COPY_ANSWER, &CopyAnswer::IllegalFileDir).await?;
+                                continue;
+                            }
+                            ResolvedPath::Existing(source) => source,
+                        };
+
+                        if source.is_dir() && !source.is_symlink() && flags & COPY_RECURSIVE == 0 {
+                            agent.send_answer(MESSAGE_COPY_ANSWER, &CopyAnswer::IsDirectory).await?;
+                            continue;
+                        }
+                        // Copying a directory creates directories:
+                        if source.is_dir() && !source.is_symlink() && !permissions.allows(Permission::Mkdir) {
+                            agent.send_answer(MESSAGE_PERMISSION_DENIED, &Permission::Mkdir).await?;
+                            continue;
+                        }
+
+                        let source_name = source.file_name().unwrap_or_default();
+                        let destination = match resolve_destination(&current_path, &root_directory, &destination_name, source_name, symlink_policy) {
+                            ResolvedDestination::ParentDoesNotExist => {
+                                agent.send_answer(MESSAGE_COPY_ANSWER, &CopyAnswer::FileDirDoesNotExist).await?;
+                                continue;
+                            }
+                            ResolvedDestination::Illegal => {
+                                agent.send_answer(MESSAGE_COPY_ANSWER, &CopyAnswer::IllegalDestination).await?;
+                                continue;
+                            }
+                            ResolvedDestination::Path(destination) => destination,
+                        };
+
+                        if is_inside(&destination, &source) {
+                            agent.send_answer(MESSAGE_COPY_ANSWER, &CopyAnswer::DestinationInsideSource).await?;
+                            continue;
+                        }
+
+                        if fs::symlink_metadata(&destination).is_ok() {
+                            if !overwrite {
+                                agent.send_answer(MESSAGE_COPY_ANSWER, &CopyAnswer::DestinationExists).await?;
+                                continue;
+                            }
+                            if !permissions.allows(Permission::Overwrite) {
+                                agent.send_answer(MESSAGE_PERMISSION_DENIED, &Permission::Overwrite).await?;
+                                continue;
+                            }
+                        }
+
+                        agent.send_copy_results(&source, &destination, overwrite).await?;
+                    }
                     MESSAGE_REMOVE => {
-                        let (file_dir_name, _) = agent.read_length_with_string(message_received)?;
+                        let (file_dir_name, message_received) = agent.read_length_with_string(message_received)?;
+                        let (flags, _) = agent.read_u64(message_received)?;
+                        let recursive = flags & REMOVE_RECURSIVE != 0;
 
-                        let mut file_path = current_path.to_path_buf();
-                        file_path.push(&file_dir_name);
+                        // A symbolic link is removed itself, its target is left untouched:
+                        let file_path = match resolve_existing_path(&current_path, &root_directory, &file_dir_name, symlink_policy, false) {
+                            ResolvedPath::DoesNotExist => {
+                                agent.send_answer(MESSAGE_REMOVE_ANSWER, &RemoveAnswer::FileDirDoesNotExist).await?;
+                                continue;
+                            }
+                            ResolvedPath::Existing(file_path) if file_path != root_directory => file_path,
+                            _ => {
+                                agent.send_answer(MESSAGE_REMOVE_ANSWER, &RemoveAnswer::IllegalFileDir).await?;
+                                continue;
+                            }
+                        };
+
+                        if recursive && !program_options.allow_recursive_delete {
+                            agent.send_answer(MESSAGE_REMOVE_ANSWER, &RemoveAnswer::RecursiveRemoveNotAllowed).await?;
+                            continue;
+                        }
+
+                        if current_path.starts_with(&file_path) {
+                            agent.send_answer(MESSAGE_REMOVE_ANSWER, &RemoveAnswer::ContainsCurrentDirectory).await?;
+                            continue;
+                        }
+
+                        let is_directory = file_path.is_dir() && !file_path.is_symlink();
+                        if recursive && is_directory {
+                            // Both computing the size and removing may take long:
+                            let preview = flags & REMOVE_PREVIEW != 0;
+                            let result = task::spawn_blocking(move || {
+                                if preview {
+                                    total_usage(&file_path).map(RemoveAnswer::Preview)
+                                } else {
+                                    fs::remove_dir_all(&file_path).map(|_| RemoveAnswer::Success)
+                                }
+                            })
+                            .await
+                            .map_err(|_| QuickTransferError::Fatal)?;
 
-                        if !fs::exists(file_path.as_path()).unwrap() {
-                            agent.send_answer(MESSAGE_REMOVE_ANSWER, &RemoveAnswer::FileDirDoesNotExist).await?;
+                            agent.send_answer(MESSAGE_REMOVE_ANSWER, &result.unwrap_or(RemoveAnswer::ErrorRemoving)).await?;
                             continue;
                         }
 
-                        let current = file_path.canonicalize().unwrap();
-                        if !current.starts_with(root_directory.clone()) {
-                            agent.send_answer(MESSAGE_REMOVE_ANSWER, &RemoveAnswer::IllegalFileDir).await?;
+                        if flags & REMOVE_PREVIEW != 0 {
+                            let answer = match total_usage(&file_path) {
+                                Ok(usage) => RemoveAnswer::Preview(usage),
+                                Err(_) => RemoveAnswer::ErrorRemoving,
+                            };
+                            agent.send_answer(MESSAGE_REMOVE_ANSWER, &answer).await?;
                             continue;
                         }
 
-                        if file_path.is_dir() {
+                        if is_directory {
                             if let Err(err) = fs::remove_dir(&file_path) {
                                 if err.kind() == ErrorKind::DirectoryNotEmpty {
                                     agent.send_answer(MESSAGE_REMOVE_ANSWER, &RemoveAnswer::DirectoryNotEmpty).await?;
@@ -480,6 +1347,250 @@ async fn handle_client_as_a_server(
     }
 }
 
+/// A result of resolving a path sent by a client.
+enum ResolvedPath {
+    DoesNotExist,
+    Illegal,
+    Existing(PathBuf),
+}
+
+/// Joins `name` to `current_path`, applying `.` and `..` lexically (like a shell does), so that
+/// `..` always leads to the parent of the displayed location, even after a symbolic link.
+fn lexical_path(current_path: &Path, name: &str) -> PathBuf {
+    let mut path = PathBuf::new();
+    for component in current_path.join(name).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                path.pop();
+            }
+            component => path.push(component),
+        }
+    }
+
+    path
+}
+
+/// Checks that a lexical `path` stays inside the root directory and that every symbolic link on
+/// it may be followed according to `symlink_policy`. If `follow_symlink` is false and `path`
+/// itself is a symbolic link, the link is not followed (the link itself is operated on).
+fn resolve_path(
+    path: PathBuf,
+    root_directory: &Path,
+    symlink_policy: SymlinkPolicy,
+    follow_symlink: bool,
+) -> ResolvedPath {
+    let Ok(relative_path) = path.strip_prefix(root_directory) else {
+        return ResolvedPath::Illegal;
+    };
+
+    let mut checked_path = root_directory.to_path_buf();
+    let mut components = relative_path.components().peekable();
+    while let Some(component) = components.next() {
+        checked_path.push(component);
+        let Ok(metadata) = fs::symlink_metadata(&checked_path) else {
+            return ResolvedPath::DoesNotExist;
+        };
+        if !metadata.is_symlink() {
+            continue;
+        }
+
+        let is_followed = follow_symlink || components.peek().is_some();
+        if symlink_policy == SymlinkPolicy::Deny
+            || (is_followed && !symlink_policy.allows_following(&checked_path, root_directory))
+        {
+            return ResolvedPath::Illegal;
+        }
+    }
+
+    ResolvedPath::Existing(path)
+}
+
+/// Resolves `name` relative to `current_path`, making sure the path stays inside the root directory.
+/// If `follow_symlink` is false and `name` points to a symbolic link, the link itself is returned.
+fn resolve_existing_path(
+    current_path: &Path,
+    root_directory: &Path,
+    name: &str,
+    symlink_policy: SymlinkPolicy,
+    follow_symlink: bool,
+) -> ResolvedPath {
+    resolve_path(
+        lexical_path(current_path, name),
+        root_directory,
+        symlink_policy,
+        follow_symlink,
+    )
+}
+
+/// A result of resolving a destination of a created/copied/moved file/directory.
+enum ResolvedDestination {
+    ParentDoesNotExist,
+    Illegal,
+    Path(PathBuf),
+}
+
+/// Resolves a lexical `path` of a file/directory which does not have to exist, but whose parent
+/// has to be a directory inside the root directory.
+fn resolve_new_path(
+    path: PathBuf,
+    root_directory: &Path,
+    symlink_policy: SymlinkPolicy,
+) -> ResolvedDestination {
+    let (Some(parent), Some(_)) = (path.parent(), path.file_name()) else {
+        return ResolvedDestination::Illegal;
+    };
+
+    match resolve_path(parent.to_path_buf(), root_directory, symlink_policy, true) {
+        ResolvedPath::DoesNotExist => ResolvedDestination::ParentDoesNotExist,
+        ResolvedPath::Illegal => ResolvedDestination::Illegal,
+        ResolvedPath::Existing(parent) if !parent.is_dir() => {
+            ResolvedDestination::ParentDoesNotExist
+        }
+        // With the `deny` policy an existing symbolic link cannot be replaced either:
+        ResolvedPath::Existing(_)
+            if symlink_policy == SymlinkPolicy::Deny
+                && fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.is_symlink()) =>
+        {
+            ResolvedDestination::Illegal
+        }
+        ResolvedPath::Existing(_) => ResolvedDestination::Path(path),
+    }
+}
+
+/// Resolves a destination `name` (relative to `current_path`) of a file/directory named
+/// `source_name`. If `name` is an existing directory, the destination is `source_name` inside it.
+/// The destination does not have to exist, but its parent has to be inside the root directory.
+fn resolve_destination(
+    current_path: &Path,
+    root_directory: &Path,
+    name: &str,
+    source_name: &OsStr,
+    symlink_policy: SymlinkPolicy,
+) -> ResolvedDestination {
+    let mut path = lexical_path(current_path, name);
+    if let ResolvedPath::Existing(directory) =
+        resolve_path(path.clone(), root_directory, symlink_policy, true)
+    {
+        if directory.is_dir() {
+            path.push(source_name);
+        }
+    }
+
+    resolve_new_path(path, root_directory, symlink_policy)
+}
+
+/// Resolves a file `name` (relative to `current_path`) written by a client. If the file is
+/// an existing symbolic link, its target is written, so the link has to be allowed to be followed.
+fn resolve_written_file(
+    current_path: &Path,
+    root_directory: &Path,
+    name: &str,
+    symlink_policy: SymlinkPolicy,
+) -> ResolvedDestination {
+    let path = lexical_path(current_path, name);
+    if !fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.is_symlink()) {
+        return resolve_new_path(path, root_directory, symlink_policy);
+    }
+
+    match resolve_path(path, root_directory, symlink_policy, true) {
+        ResolvedPath::Existing(path) => ResolvedDestination::Path(path),
+        _ => ResolvedDestination::Illegal,
+    }
+}
+
+/// Checks whether `destination` is `source` or is inside it (after resolving symbolic links
+/// leading to both of them).
+fn is_inside(destination: &Path, source: &Path) -> bool {
+    let canonical_path = |path: &Path| match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => parent.canonicalize().ok().map(|parent| parent.join(name)),
+        _ => path.canonicalize().ok(),
+    };
+
+    match (canonical_path(destination), canonical_path(source)) {
+        (Some(destination), Some(source)) => destination.starts_with(source),
+        _ => destination.starts_with(source),
+    }
+}
+
+/// Reads a bounded (by `MAX_READ_SIZE`) fragment of a file.
+fn read_file_fragment(file: &mut File, range: &ReadRange) -> io::Result<FileFragment> {
+    let file_size = file.metadata()?.len();
+    let max_read_size: u64 = MAX_READ_SIZE.try_into().unwrap();
+
+    let (offset, length) = match range {
+        ReadRange::Bytes { offset, length } => ((*offset).min(file_size), *length),
+        ReadRange::HeadLines(_) => (0, max_read_size),
+        ReadRange::TailLines(_) => {
+            let offset = file_size.saturating_sub(max_read_size);
+            (offset, file_size - offset)
+        }
+    };
+    let bytes_to_read = length.min(max_read_size).min(file_size - offset);
+
+    let mut content = vec![0_u8; bytes_to_read.try_into().unwrap()];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut content)?;
+
+    let mut fragment = FileFragment {
+        offset,
+        truncated: bytes_to_read < length && offset + bytes_to_read < file_size,
+        content,
+        file_size,
+    };
+
+    match *range {
+        ReadRange::Bytes { .. } => {}
+        ReadRange::HeadLines(lines) => {
+            let lines = usize::try_from(lines).unwrap_or(usize::MAX);
+            let end = if lines == 0 {
+                Some(0)
+            } else {
+                fragment
+                    .content
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, byte)| **byte == b'\n')
+                    .map(|(position, _)| position + 1)
+                    .nth(lines - 1)
+            };
+
+            if let Some(end) = end {
+                fragment.content.truncate(end);
+                fragment.truncated = u64::try_from(end).unwrap() < file_size;
+            }
+        }
+        ReadRange::TailLines(lines) => {
+            let lines = usize::try_from(lines).unwrap_or(usize::MAX);
+            // A newline ending the file doesn't start a new line:
+            let content_end = if fragment.content.ends_with(b"\n") {
+                fragment.content.len() - 1
+            } else {
+                fragment.content.len()
+            };
+            let start = if lines == 0 {
+                Some(fragment.content.len())
+            } else {
+                fragment.content[..content_end]
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .filter(|(_, byte)| **byte == b'\n')
+                    .map(|(position, _)| position + 1)
+                    .nth(lines - 1)
+            };
+
+            if let Some(start) = start {
+                fragment.offset += u64::try_from(start).unwrap();
+                fragment.content.drain(..start);
+            }
+            fragment.truncated = fragment.offset > 0;
+        }
+    }
+
+    Ok(fragment)
+}
+
 /// Pre-prints user help so as not to do it every time.
 fn preprint_user_help(help_msg: &mut String) {
     help_msg.push_str("Available commands:\n");
@@ -491,6 +1602,9 @@ fn preprint_user_help(help_msg: &mut String) {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::common::{
+        address_filter::AddressFilter, key_exchange::RekeyLimits, rate_limit::RateLimits,
+    };
 
     #[tokio::test]
     async fn test_create_a_listener() {
@@ -501,9 +1615,132 @@ mod test {
             root_directory: String::from("./"),
             timeout: 5,
             aes_key: None,
+            passphrase: None,
+            require_encryption: false,
+            command: vec![],
+            allow_recursive_delete: false,
+            symlink_policy: SymlinkPolicy::FollowWithinRoot,
+            rekey_limits: RekeyLimits::default(),
+            tls: false,
+            tls_directory: PathBuf::new(),
+            tls_fingerprint: None,
+            users: None,
+            user_name: None,
+            password: None,
+            permissions: Permissions::FULL,
+            address_filter: AddressFilter::default(),
+            rate_limits: RateLimits::default(),
         };
 
         let listener = create_a_listener(&program_options).await;
         assert!(listener.is_ok());
     }
+
+    #[test]
+    fn test_record_handshake() {
+        let limiter = HandshakeLimiter::new(RateLimits {
+            connections_per_minute: 0,
+            max_failures: 3,
+            lockout: Duration::from_secs(60),
+        });
+        let address: IpAddr = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+
+        // Connections without authentication between failed attempts:
+        let mut locked_out = false;
+        for _ in 0..3 {
+            assert_eq!(limiter.admit(address, now), Admission::Allowed);
+            record_handshake(&limiter, address, false);
+            assert_eq!(limiter.admit(address, now), Admission::Allowed);
+            locked_out = limiter.record_failure(address, now);
+        }
+        assert!(locked_out);
+        assert_eq!(limiter.admit(address, now), Admission::LockedOut);
+
+        // An authenticated client resets the failures:
+        let other_address: IpAddr = "10.0.0.2".parse().unwrap();
+        for _ in 0..3 {
+            assert!(!limiter.record_failure(other_address, now));
+            record_handshake(&limiter, other_address, true);
+        }
+    }
+
+    #[test]
+    fn test_read_file_fragment() {
+        let file_path = std::env::temp_dir().join("quick_transfer_test_read_file_fragment");
+        fs::write(&file_path, b"one\ntwo\nthree\nfour\n").unwrap();
+        let mut file = File::open(&file_path).unwrap();
+
+        let head = read_file_fragment(&mut file, &ReadRange::HeadLines(2)).unwrap();
+        assert_eq!(head.content, b"one\ntwo\n");
+        assert!(head.truncated);
+
+        let tail = read_file_fragment(&mut file, &ReadRange::TailLines(2)).unwrap();
+        assert_eq!(tail.content, b"three\nfour\n");
+        assert_eq!(tail.offset, 8);
+        assert!(tail.truncated);
+
+        let everything = read_file_fragment(&mut file, &ReadRange::TailLines(10)).unwrap();
+        assert_eq!(everything.content, b"one\ntwo\nthree\nfour\n");
+        assert!(!everything.truncated);
+
+        let bytes = read_file_fragment(
+            &mut file,
+            &ReadRange::Bytes {
+                offset: 4,
+                length: 3,
+            },
+        )
+        .unwrap();
+        assert_eq!(bytes.content, b"two");
+        assert_eq!(bytes.file_size, 19);
+
+        fs::remove_file(&file_path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_existing_path() {
+        let directory = std::env::temp_dir().join("quick_transfer_test_resolve_existing_path");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("root/sub")).unwrap();
+        fs::write(directory.join("outside.txt"), b"outside").unwrap();
+        std::os::unix::fs::symlink("sub", directory.join("root/inside_link")).unwrap();
+        std::os::unix::fs::symlink("../outside.txt", directory.join("root/outside_link")).unwrap();
+        let root = directory.join("root").canonicalize().unwrap();
+
+        let is_existing = |name: &str, policy: SymlinkPolicy, follow_symlink: bool| {
+            matches!(
+                resolve_existing_path(&root, &root, name, policy, follow_symlink),
+                ResolvedPath::Existing(_)
+            )
+        };
+
+        assert!(!is_existing(
+            "../outside.txt",
+            SymlinkPolicy::FollowAll,
+            true
+        ));
+        assert!(is_existing("sub/..", SymlinkPolicy::Deny, true));
+        assert!(is_existing(
+            "inside_link",
+            SymlinkPolicy::FollowWithinRoot,
+            true
+        ));
+        assert!(!is_existing(
+            "outside_link",
+            SymlinkPolicy::FollowWithinRoot,
+            true
+        ));
+        assert!(is_existing("outside_link", SymlinkPolicy::FollowAll, true));
+        assert!(!is_existing("inside_link", SymlinkPolicy::ShowAsLink, true));
+        assert!(is_existing(
+            "outside_link",
+            SymlinkPolicy::ShowAsLink,
+            false
+        ));
+        assert!(!is_existing("inside_link", SymlinkPolicy::Deny, false));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
 }